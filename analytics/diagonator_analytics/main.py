@@ -30,7 +30,9 @@ def requirement_data():
 
     with sqlite3.connect(ANALYTICS_FILE) as conn:
         data = pd.read_sql_query(
-            "SELECT * FROM requirement_log WHERE date >= ? AND date <= ?",
+            "SELECT date, time, requirement AS name FROM request_log "
+            "WHERE type = 'CompleteRequirement' AND success = 1 "
+            "AND date >= ? AND date <= ?",
             conn,
             params=(from_date, to_date),
             parse_dates={"date": "%Y-%m-%d"},
@@ -66,7 +68,8 @@ def deactivation_data():
 
     with sqlite3.connect(ANALYTICS_FILE) as conn:
         data = pd.read_sql_query(
-            "SELECT * FROM deactivate_log WHERE date >= ? AND date <= ?",
+            "SELECT date, time, state, reason, reason_details AS details FROM request_log "
+            "WHERE type = 'Deactivate' AND success = 1 AND date >= ? AND date <= ?",
             conn,
             params=(from_date, to_date),
         )
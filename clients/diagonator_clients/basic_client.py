@@ -1,31 +1,14 @@
-import sqlite3
 import sys
 
-from .utils import ANALYTICS_FILE, get_datetime_pair, send_request
-
-
-def log_event_to_table(table_name):
-    with sqlite3.connect(ANALYTICS_FILE) as conn:
-        conn.execute(f"""CREATE TABLE IF NOT EXISTS {table_name}(
-                date TEXT NOT NULL,
-                time INTEGER NOT NULL) STRICT""")
-        conn.execute(
-            f"INSERT INTO {table_name}(date,time) VALUES (?, ?)", get_datetime_pair()
-        )
+from .utils import send_request
 
 
 def unlock_timer():
-    response = send_request({"type": "UnlockTimer"})
-    print(response)
-    if response["type"] == "Success":
-        log_event_to_table("unlock_log")
+    print(send_request({"type": "UnlockTimer"}))
 
 
 def lock_timer():
-    response = send_request({"type": "LockTimer"})
-    print(response)
-    if response["type"] == "Success":
-        log_event_to_table("lock_log")
+    print(send_request({"type": "LockTimer"}))
 
 
 def get_info():
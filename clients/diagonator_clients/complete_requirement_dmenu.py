@@ -1,22 +1,8 @@
 import operator
-import sqlite3
 import subprocess
 import sys
 
-from .utils import ANALYTICS_FILE, get_datetime_pair, send_request
-
-
-def log_to_db(choice):
-    with sqlite3.connect(ANALYTICS_FILE) as conn:
-        conn.execute("""CREATE TABLE IF NOT EXISTS requirement_log(
-                date TEXT NOT NULL,
-                time INTEGER NOT NULL,
-                name TEXT NOT NULL) STRICT""")
-        conn.execute(
-            "INSERT INTO requirement_log(date,time,name) VALUES (?, ?, ?)",
-            get_datetime_pair() + (choice,),
-        )
-
+from .utils import send_request
 
 DMENU_CMD = ["dmenu"] + sys.argv[1:]
 
@@ -40,7 +26,6 @@ if info["type"] == "Info":
             res = send_request({"type": "CompleteRequirement", "id": choice_req["id"]})
             if res["type"] == "Success":
                 print(f"Successfully completed requirement: {choice}")
-                log_to_db(choice)
             else:
                 print(res)
         except StopIteration:
@@ -3,14 +3,14 @@ import sys
 
 import socketio
 
-from .utils import SERVER_URL
+from .utils import SERVER_URL, TOKEN
 
 DIAGONATOR_CMD = ["diagonator"] + sys.argv[1:]
 
 proc = None
 
 with socketio.SimpleClient() as sio:
-    sio.connect(SERVER_URL)
+    sio.connect(SERVER_URL, auth={"token": TOKEN})
     while True:
         event = sio.receive()
         if event[0] == "info_update":
@@ -1,7 +1,6 @@
 import sys
 
-from .basic_client import unlock_timer
-from .utils import prompt_dmenu_time
+from .utils import prompt_dmenu_challenge, send_request
 
-if prompt_dmenu_time(sys.argv[1:]):
-    unlock_timer()
+challenge = prompt_dmenu_challenge("UnlockTimer", sys.argv[1:])
+print(send_request({"type": "UnlockTimer", "challenge": challenge}))
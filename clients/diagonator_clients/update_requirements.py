@@ -6,7 +6,8 @@ with sqlite3.connect(ANALYTICS_FILE) as conn:
     completed = {
         c[0]
         for c in conn.execute(
-            "SELECT name FROM requirement_log WHERE date = ?",
+            "SELECT requirement FROM request_log "
+            "WHERE type = 'CompleteRequirement' AND success = 1 AND date = ?",
             get_datetime_pair()[:1],
         ).fetchall()
     }
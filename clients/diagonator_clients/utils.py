@@ -1,17 +1,44 @@
 import datetime
+import http.client
+import json as jsonlib
 import os
+import socket
 import subprocess
 
-import pytest
 import requests
 
 SERVER_URL = os.getenv("DIAGONATOR_SERVER_URL", "http://localhost:3000")
 ANALYTICS_FILE = os.getenv("DIAGONATOR_ANALYTICS_FILE")
+TOKEN = os.getenv("DIAGONATOR_TOKEN", "")
+SOCKET_PATH = os.getenv("DIAGONATOR_SOCKET_PATH")
+
+
+class UnixHTTPConnection(http.client.HTTPConnection):
+    """An HTTP connection over a UNIX domain socket"""
+
+    def __init__(self, socket_path: str):
+        super().__init__("localhost")
+        self.socket_path = socket_path
+
+    def connect(self):
+        self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        self.sock.connect(self.socket_path)
 
 
 def send_request(json) -> dict:
     """Sends a JSON request to the server and returns the decoded JSON response"""
-    return requests.post(SERVER_URL, json=json).json()
+    if SOCKET_PATH is not None:
+        conn = UnixHTTPConnection(SOCKET_PATH)
+        conn.request(
+            "POST",
+            "/",
+            body=jsonlib.dumps(json),
+            headers={"Content-Type": "application/json"},
+        )
+        return jsonlib.loads(conn.getresponse().read())
+    return requests.post(
+        SERVER_URL, json=json, headers={"Authorization": f"Bearer {TOKEN}"}
+    ).json()
 
 
 def get_datetime_pair():
@@ -22,92 +49,23 @@ def get_datetime_pair():
     return (now.strftime("%Y-%m-%d"), (now - midnight).seconds)
 
 
-def get_answer_impl(t, wake_up, bedtime):
-    def plural(n: int | str, unit: str):
-        if str(n) == "1":
-            return f"1 {unit}"
-        else:
-            return f"{n} {unit}s"
-
-    def time_until(t, target_hour):
-        h = t.hour
-        m = t.minute
-        if h >= target_hour:
-            h -= 24
-        diff_minutes = (target_hour - h) * 60 - m
-        total = diff_minutes // 30
-        if total == 0:
-            return plural(diff_minutes, "minute")
-        elif total % 2 == 0:
-            return plural(total // 2, "hour")
-        else:
-            return plural(str(total // 2) + ".5", "hour")
-
-    h = t.hour
-    m = t.minute
-    if h < wake_up - 3 or h >= bedtime:
-        return f"{time_until(t, wake_up)} until {wake_up:02}:00 - no more work"
-    elif h >= bedtime - 2:
-        return f"{time_until(t, bedtime)} until bedtime - no more work"
-    elif h >= bedtime - 4:
-        return f"{time_until(t, bedtime)} until bedtime"
-    else:
-        n = -(-(h * 60 + m) // 30)
-        h = n // 2
-        m = n % 2 * 30
-        return f"{h:02}:{m:02}"
-
-
-def get_answer(t):
-    """Returns the answer expected by prompt_dmenu_time at time t"""
-    return get_answer_impl(t, 7, 22)
-
-
-def prompt_dmenu_time(dmenu_options: list[str]) -> bool:
-    """Creates a dmenu prompt asking for the current time, rounded to the next half hour.
-    Returns a bool indicating whether the user input the correct time.
+def prompt_dmenu_challenge(action: str, dmenu_options: list[str]) -> dict | None:
+    """Requests a challenge for the given action ("UnlockTimer" or "Deactivate") from the server
+    and uses dmenu to ask for the answer. Returns the challenge answer to include in the request,
+    or None if the server doesn't require a challenge for that action.
     """
 
+    response = send_request({"type": "GetChallenge", "action": action})
+    if response["type"] != "Challenge":
+        return None
+    challenge = response["challenge"]
     answer = (
         subprocess.run(
-            ["dmenu"] + dmenu_options,
+            ["dmenu", "-p", challenge["prompt"]] + dmenu_options,
             input="",
             capture_output=True,
         )
         .stdout.decode()
         .strip("\n")
     )
-    t = datetime.datetime.now()
-    t2 = t - datetime.timedelta(minutes=1)
-    return answer in (get_answer(t), get_answer(t2))
-
-
-@pytest.mark.parametrize(
-    "hm,expected",
-    [
-        ("18:30", "18:30"),
-        ("18:31", "19:00"),
-        ("18:59", "19:00"),
-        ("19:00", "4 hours until bedtime"),
-        ("19:01", "3.5 hours until bedtime"),
-        ("20:59", "2 hours until bedtime"),
-        ("21:00", "2 hours until bedtime - no more work"),
-        ("21:01", "1.5 hours until bedtime - no more work"),
-        ("22:00", "1 hour until bedtime - no more work"),
-        ("22:01", "0.5 hours until bedtime - no more work"),
-        ("22:30", "0.5 hours until bedtime - no more work"),
-        ("22:31", "29 minutes until bedtime - no more work"),
-        ("22:59", "1 minute until bedtime - no more work"),
-        ("23:00", "9 hours until 08:00 - no more work"),
-        ("23:01", "8.5 hours until 08:00 - no more work"),
-        ("23:59", "8 hours until 08:00 - no more work"),
-        ("0:00", "8 hours until 08:00 - no more work"),
-        ("2:29", "5.5 hours until 08:00 - no more work"),
-        ("2:42", "5 hours until 08:00 - no more work"),
-        ("4:59", "3 hours until 08:00 - no more work"),
-        ("5:00", "05:00"),
-    ],
-)
-def test_get_answer(hm: str, expected: str):
-    t = datetime.datetime.strptime(hm, "%H:%M")
-    assert get_answer_impl(t, 8, 23) == expected
+    return {"id": challenge["id"], "answer": answer}
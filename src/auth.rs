@@ -0,0 +1,98 @@
+use crate::config::{config_dir, LoadConfigError};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use toml_edit::easy as toml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    /// can get information about the current state, but can't change it
+    ReadOnly,
+    /// can send any request
+    Control,
+}
+
+/// Tokens that clients must present to use the API, stored in `tokens.toml` in the
+/// configuration directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tokens {
+    control: Vec<String>,
+    read_only: Vec<String>,
+}
+
+impl Tokens {
+    pub fn file_path() -> Result<PathBuf, LoadConfigError> {
+        let mut path = config_dir()?;
+        path.push("tokens.toml");
+        Ok(path)
+    }
+    /// Loads the tokens file, generating it first if it doesn't exist.
+    pub fn in_config_dir() -> Result<Self, LoadConfigError> {
+        let path = Self::file_path()?;
+        if !path.exists() {
+            Self::generate(&path)?;
+        }
+        eprintln!("Loading access tokens from {}", path.display());
+        Self::read(&path)
+    }
+    pub fn read(path: &PathBuf) -> Result<Self, LoadConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| LoadConfigError::ReadError(path.clone(), err))?;
+        Ok(toml::from_str(&contents)?)
+    }
+    fn generate(path: &PathBuf) -> Result<(), LoadConfigError> {
+        eprintln!("Generating access tokens at {}", path.display());
+        let tokens = Self {
+            control: vec![generate_token()],
+            read_only: vec![generate_token()],
+        };
+        let contents = toml::to_string_pretty(&tokens)?;
+        write_private(path, contents).map_err(|err| LoadConfigError::WriteError(path.clone(), err))
+    }
+    pub fn control_token(&self) -> Option<&str> {
+        self.control.first().map(String::as_str)
+    }
+    /// Returns the access granted by `token`, or `None` if the token is invalid.
+    pub fn access(&self, token: &str) -> Option<Access> {
+        if self.control.iter().any(|t| tokens_equal(t, token)) {
+            Some(Access::Control)
+        } else if self.read_only.iter().any(|t| tokens_equal(t, token)) {
+            Some(Access::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+fn generate_token() -> String {
+    let bytes: [u8; 24] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compares two tokens in an amount of time that doesn't depend on where they differ.
+fn tokens_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Writes a file that only the current user can read, since it contains secrets.
+#[cfg(unix)]
+fn write_private(path: &PathBuf, contents: String) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &PathBuf, contents: String) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
@@ -0,0 +1,315 @@
+use clap::{Parser, Subcommand};
+use diagonator_server::auth::Tokens;
+use diagonator_server::challenge::{ChallengeAction, ChallengeAnswer};
+use diagonator_server::config::{config_file_path, read_config};
+use diagonator_server::line_protocol::{ConnectionRequest, Event};
+use diagonator_server::manager::{CurrentInfo, CurrentStateReason};
+use diagonator_server::server::{Request, Response};
+use diagonator_server::time::{Duration, HourMinute};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+/// Command-line client for diagonator-server. It connects to the server's line protocol
+/// listener, which is read from the server's configuration file unless --socket or --address
+/// is given.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Connect to the line protocol listener on this Unix socket
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    /// Connect to the line protocol listener on this TCP address
+    #[arg(long, global = true, conflicts_with = "socket")]
+    address: Option<String>,
+    /// Token to authenticate with over TCP [default: the server's first control token]
+    #[arg(long, global = true, env = "DIAGONATOR_TOKEN")]
+    token: Option<String>,
+    /// Print responses as JSON
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Unlock the break timer
+    Unlock,
+    /// Lock the break timer
+    Lock,
+    /// Show the current state and requirements
+    Info,
+    /// Mark a requirement as completed
+    Complete {
+        /// ID or name of the requirement
+        requirement: String,
+    },
+    /// Add a one-time requirement
+    Add {
+        name: String,
+        /// Completion deadline (HH:MM)
+        due: HourMinute,
+        /// Earliest time at which the requirement can be completed (HH:MM)
+        #[arg(long)]
+        available_from: Option<HourMinute>,
+    },
+    /// Deactivate the server
+    Deactivate {
+        /// Duration of the deactivation in minutes
+        minutes: i64,
+    },
+    /// Print the current state whenever it changes
+    Watch,
+}
+
+struct Connection {
+    reader: BufReader<Box<dyn Read>>,
+    writer: Box<dyn Write>,
+}
+
+impl Connection {
+    fn open(cli: &Cli) -> Result<Self, String> {
+        if let Some(socket) = &cli.socket {
+            return Self::open_unix(socket);
+        }
+        if let Some(address) = &cli.address {
+            return Self::open_tcp(address, cli.token.as_deref());
+        }
+        let config = config_file_path()
+            .and_then(|path| read_config(&path))
+            .map_err(|err| err.to_string())?;
+        if let Some(socket) = &config.line_socket_path {
+            Self::open_unix(socket)
+        } else if let Some(address) = &config.line_bind_on {
+            Self::open_tcp(address, cli.token.as_deref())
+        } else {
+            Err(
+                "The server's configuration sets neither line_socket_path nor line_bind_on, \
+                 so use --socket or --address, or add line_bind_on = \"127.0.0.1:3001\" to it."
+                    .to_owned(),
+            )
+        }
+    }
+    #[cfg(unix)]
+    fn open_unix(path: &PathBuf) -> Result<Self, String> {
+        let stream = std::os::unix::net::UnixStream::connect(path)
+            .map_err(|err| format!("Unable to connect to {}: {}", path.display(), err))?;
+        let reader = stream.try_clone().map_err(|err| err.to_string())?;
+        Ok(Self {
+            reader: BufReader::new(Box::new(reader)),
+            writer: Box::new(stream),
+        })
+    }
+    #[cfg(not(unix))]
+    fn open_unix(path: &PathBuf) -> Result<Self, String> {
+        Err(format!(
+            "Unable to connect to {}: Unix sockets are not supported on this platform",
+            path.display()
+        ))
+    }
+    fn open_tcp(address: &str, token: Option<&str>) -> Result<Self, String> {
+        let token = match token {
+            Some(token) => token.to_owned(),
+            None => Tokens::file_path()
+                .and_then(|path| Tokens::read(&path))
+                .map_err(|err| err.to_string())?
+                .control_token()
+                .ok_or("The server's tokens file has no control tokens")?
+                .to_owned(),
+        };
+        let stream = TcpStream::connect(address)
+            .map_err(|err| format!("Unable to connect to {}: {}", address, err))?;
+        let reader = stream.try_clone().map_err(|err| err.to_string())?;
+        let mut connection = Self {
+            reader: BufReader::new(Box::new(reader)),
+            writer: Box::new(stream),
+        };
+        match connection.request(&ConnectionRequest::Authenticate { token })? {
+            Response::Success => Ok(connection),
+            response => Err(format!("Unable to authenticate: {:?}", response)),
+        }
+    }
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<(), String> {
+        let mut line = serde_json::to_string(message).map_err(|err| err.to_string())?;
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|err| format!("Unable to send request: {}", err))
+    }
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err("The server closed the connection".to_owned()),
+            Ok(_) => serde_json::from_str(&line)
+                .map_err(|err| format!("Unable to parse message from server: {}", err)),
+            Err(err) => Err(format!("Unable to receive message from server: {}", err)),
+        }
+    }
+    fn request<T: Serialize>(&mut self, message: &T) -> Result<Response, String> {
+        self.send(message)?;
+        self.receive()
+    }
+}
+
+/// Asks for the answer to a challenge on the terminal, if the server requires one for `action`.
+fn answer_challenge(
+    connection: &mut Connection,
+    action: ChallengeAction,
+) -> Result<Option<ChallengeAnswer>, String> {
+    match connection.request(&Request::GetChallenge { action })? {
+        Response::Challenge { challenge } => {
+            eprint!("{} ", challenge.prompt());
+            let mut answer = String::new();
+            std::io::stdin()
+                .read_line(&mut answer)
+                .map_err(|err| err.to_string())?;
+            Ok(Some(ChallengeAnswer {
+                id: challenge.id(),
+                answer: answer.trim().to_owned(),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Finds the ID of a requirement, given either its ID or the name of an incomplete requirement.
+fn find_requirement(connection: &mut Connection, requirement: &str) -> Result<u64, String> {
+    if let Ok(id) = requirement.parse() {
+        return Ok(id);
+    }
+    match connection.request(&Request::GetInfo)? {
+        Response::Info { info } => info
+            .requirements()
+            .iter()
+            .find(|req| !req.complete() && req.name() == requirement)
+            .map(|req| req.id())
+            .ok_or_else(|| format!("No incomplete requirement is named '{}'", requirement)),
+        response => Err(format!("Unexpected response: {:?}", response)),
+    }
+}
+
+fn describe_reason(info: &CurrentInfo) -> String {
+    match info.reason() {
+        CurrentStateReason::BreakTimer => "break timer".to_owned(),
+        CurrentStateReason::RequirementNotMet { id } => format!(
+            "requirement '{}'",
+            info.requirement_name(*id).unwrap_or("unknown")
+        ),
+        CurrentStateReason::LockedTimeRange { id } => format!("locked time range {}", id),
+        CurrentStateReason::NoConstraints => "no constraints".to_owned(),
+    }
+}
+
+fn summarize(info: &CurrentInfo) -> String {
+    let mut summary = format!("{:?}", info.state());
+    if let Some(until) = info.until() {
+        summary += &format!(" until {}", until.format_hm());
+    }
+    summary += &format!(" ({})", describe_reason(info));
+    if let Some(deactivated_until) = info.deactivated_until() {
+        summary += &format!(", deactivated until {}", deactivated_until.format_hm());
+    }
+    summary
+}
+
+fn print_info(info: &CurrentInfo) {
+    println!("{}", summarize(info));
+    if !info.requirements().is_empty() {
+        println!("Requirements:");
+        for req in info.requirements() {
+            let mut line = format!(
+                "  [{}] {}: {} (due {}",
+                if req.complete() { "x" } else { " " },
+                req.id(),
+                req.name(),
+                req.due().format_hm()
+            );
+            if let Some(available_from) = req.available_from() {
+                if !req.complete() && !req.completable() {
+                    line += &format!(", available from {}", available_from.format_hm());
+                }
+            }
+            println!("{})", line);
+        }
+    }
+}
+
+/// Prints a response, returning whether it was successful.
+fn print_response(response: &Response, json: bool) -> bool {
+    if json {
+        println!("{}", serde_json::to_string(response).unwrap());
+    }
+    match response {
+        Response::Error { msg, .. } => {
+            if !json {
+                eprintln!("Error: {}", msg);
+            }
+            false
+        }
+        Response::Info { info } => {
+            if !json {
+                print_info(info);
+            }
+            true
+        }
+        _ => true,
+    }
+}
+
+fn run(cli: &Cli) -> Result<bool, String> {
+    let mut connection = Connection::open(cli)?;
+    let request = match &cli.command {
+        Command::Unlock => Request::UnlockTimer {
+            challenge: answer_challenge(&mut connection, ChallengeAction::UnlockTimer)?,
+        },
+        Command::Lock => Request::LockTimer,
+        Command::Info => Request::GetInfo,
+        Command::Complete { requirement } => Request::CompleteRequirement {
+            id: find_requirement(&mut connection, requirement)?,
+        },
+        Command::Add {
+            name,
+            due,
+            available_from,
+        } => Request::AddRequirement {
+            name: name.clone(),
+            due: *due,
+            available_from: *available_from,
+        },
+        Command::Deactivate { minutes } => Request::Deactivate {
+            duration: Duration::from_minutes(*minutes),
+            challenge: answer_challenge(&mut connection, ChallengeAction::Deactivate)?,
+        },
+        Command::Watch => {
+            let response = connection.request(&ConnectionRequest::Subscribe)?;
+            if !matches!(response, Response::Success) {
+                return Ok(print_response(&response, cli.json));
+            }
+            loop {
+                let Event::InfoUpdate { info, .. } = connection.receive()?;
+                if cli.json {
+                    println!("{}", serde_json::to_string(&info).unwrap());
+                } else {
+                    println!("{}", summarize(&info));
+                }
+            }
+        }
+    };
+    let response = connection.request(&request)?;
+    Ok(print_response(&response, cli.json))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::time::{Duration, HourMinute, Timestamp};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PHRASES: &[&str] = &[
+    "I am choosing to give up my break",
+    "The work will still be here after I rest",
+    "I have thought about this and I still want to continue",
+];
+
+/// The kinds of challenges that can be required before an action is allowed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ChallengeKind {
+    /// multiply two 2-digit numbers
+    Arithmetic,
+    /// type the current time, rounded up to the next half hour, or how long is left until
+    /// bedtime or wake-up time when it is late
+    Time {
+        wake_up_hour: Option<u32>,
+        bedtime_hour: Option<u32>,
+    },
+    /// type one of the phrases exactly
+    Phrase { phrases: Option<Vec<String>> },
+    /// wait for a number of seconds before answering
+    Wait { seconds: i64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeAction {
+    UnlockTimer,
+    Deactivate,
+}
+
+/// A client's response to a challenge, sent along with the action that required it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChallengeAnswer {
+    pub id: u64,
+    #[serde(default)]
+    pub answer: String,
+}
+
+/// A challenge as it is sent to clients, without its expected answer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IssuedChallenge {
+    id: u64,
+    action: ChallengeAction,
+    prompt: String,
+    /// the challenge cannot be answered before this time
+    valid_from: Timestamp,
+    expires: Timestamp,
+}
+
+impl IssuedChallenge {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+}
+
+enum ExpectedAnswer {
+    Exact(String),
+    Time {
+        wake_up_hour: u32,
+        bedtime_hour: u32,
+    },
+    Any,
+}
+
+struct PendingChallenge {
+    issued: IssuedChallenge,
+    expected: ExpectedAnswer,
+}
+
+/// Which challenge each action requires. `None` means that the action doesn't require one.
+pub struct ChallengePolicy {
+    pub unlock_timer: Option<ChallengeKind>,
+    pub deactivate: Option<ChallengeKind>,
+    /// how long a challenge can be answered for, after it becomes valid
+    pub expiry: Duration,
+}
+
+impl ChallengePolicy {
+    fn kind_for(&self, action: ChallengeAction) -> Option<&ChallengeKind> {
+        match action {
+            ChallengeAction::UnlockTimer => self.unlock_timer.as_ref(),
+            ChallengeAction::Deactivate => self.deactivate.as_ref(),
+        }
+    }
+}
+
+/// Keeps track of the challenges that have been issued but not answered yet.
+pub struct Challenges {
+    pending: Vec<PendingChallenge>,
+    next_id: u64,
+}
+
+impl Default for Challenges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Challenges {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            next_id: 1,
+        }
+    }
+    pub fn issue(
+        &mut self,
+        policy: &ChallengePolicy,
+        current_time: Timestamp,
+        action: ChallengeAction,
+    ) -> Result<IssuedChallenge, String> {
+        let kind = policy
+            .kind_for(action)
+            .ok_or_else(|| format!("{:?} does not require a challenge.", action))?;
+        self.pending
+            .retain(|challenge| challenge.issued.expires >= current_time);
+        let mut rng = rand::thread_rng();
+        let mut valid_from = current_time;
+        let (prompt, expected) = match kind {
+            ChallengeKind::Arithmetic => {
+                let a: i64 = rng.gen_range(12..100);
+                let b: i64 = rng.gen_range(12..100);
+                (
+                    format!("What is {} * {}?", a, b),
+                    ExpectedAnswer::Exact((a * b).to_string()),
+                )
+            }
+            ChallengeKind::Time {
+                wake_up_hour,
+                bedtime_hour,
+            } => (
+                "What time is it? (rounded up to the next half hour)".to_owned(),
+                ExpectedAnswer::Time {
+                    wake_up_hour: wake_up_hour.unwrap_or(7),
+                    bedtime_hour: bedtime_hour.unwrap_or(22),
+                },
+            ),
+            ChallengeKind::Phrase { phrases } => {
+                let phrase = match phrases {
+                    Some(phrases) if !phrases.is_empty() => {
+                        phrases.choose(&mut rng).unwrap().clone()
+                    }
+                    _ => DEFAULT_PHRASES.choose(&mut rng).unwrap().to_string(),
+                };
+                (
+                    format!("Type the following phrase: {}", phrase),
+                    ExpectedAnswer::Exact(phrase),
+                )
+            }
+            ChallengeKind::Wait { seconds } => {
+                valid_from = current_time + Duration::from_seconds(*seconds);
+                (
+                    format!(
+                        "Wait until {} before answering with anything.",
+                        valid_from.format_hm()
+                    ),
+                    ExpectedAnswer::Any,
+                )
+            }
+        };
+        let issued = IssuedChallenge {
+            id: self.next_id,
+            action,
+            prompt,
+            valid_from,
+            expires: valid_from + policy.expiry,
+        };
+        self.next_id += 1;
+        self.pending.push(PendingChallenge {
+            issued: issued.clone(),
+            expected,
+        });
+        Ok(issued)
+    }
+    /// Checks the answer to a challenge for `action`, if the policy requires one.
+    /// Each challenge can only be answered once, whether or not the answer is correct.
+    pub fn verify(
+        &mut self,
+        policy: &ChallengePolicy,
+        current_time: Timestamp,
+        action: ChallengeAction,
+        answer: Option<&ChallengeAnswer>,
+    ) -> Result<(), String> {
+        if policy.kind_for(action).is_none() {
+            return Ok(());
+        }
+        let answer = answer.ok_or_else(|| {
+            format!(
+                "{:?} requires a challenge. Request one with GetChallenge.",
+                action
+            )
+        })?;
+        let index = self
+            .pending
+            .iter()
+            .position(|challenge| {
+                challenge.issued.id == answer.id && challenge.issued.action == action
+            })
+            .ok_or_else(|| format!("Challenge with ID {} not found.", answer.id))?;
+        let challenge = self.pending.remove(index);
+        if current_time < challenge.issued.valid_from {
+            return Err(format!(
+                "Challenge cannot be answered before {}.",
+                challenge.issued.valid_from.format_hm()
+            ));
+        }
+        if current_time > challenge.issued.expires {
+            return Err("Challenge has expired.".to_owned());
+        }
+        let answer = answer.answer.trim();
+        let correct = match challenge.expected {
+            ExpectedAnswer::Exact(expected) => answer == expected,
+            ExpectedAnswer::Time {
+                wake_up_hour,
+                bedtime_hour,
+            } => {
+                // also accept the answer from a minute ago, in case the time changed while
+                // the answer was being typed
+                [current_time, current_time + Duration::from_minutes(-1)]
+                    .iter()
+                    .any(|t| answer == time_answer(t.get_hm(), wake_up_hour, bedtime_hour))
+            }
+            ExpectedAnswer::Any => true,
+        };
+        if correct {
+            Ok(())
+        } else {
+            Err("Incorrect answer to challenge.".to_owned())
+        }
+    }
+}
+
+/// Returns the expected answer to a `Time` challenge at time `t`.
+fn time_answer(t: HourMinute, wake_up_hour: u32, bedtime_hour: u32) -> String {
+    fn plural(n: &str, unit: &str) -> String {
+        if n == "1" {
+            format!("1 {}", unit)
+        } else {
+            format!("{} {}s", n, unit)
+        }
+    }
+    fn time_until(t: HourMinute, target_hour: i64) -> String {
+        let mut h = t.hour() as i64;
+        if h >= target_hour {
+            h -= 24;
+        }
+        let diff_minutes = (target_hour - h) * 60 - t.minute() as i64;
+        let total = diff_minutes / 30;
+        if total == 0 {
+            plural(&diff_minutes.to_string(), "minute")
+        } else if total % 2 == 0 {
+            plural(&(total / 2).to_string(), "hour")
+        } else {
+            plural(&format!("{}.5", total / 2), "hour")
+        }
+    }
+    let (wake_up, bedtime) = (wake_up_hour as i64, bedtime_hour as i64);
+    let h = t.hour() as i64;
+    if h < wake_up - 3 || h >= bedtime {
+        format!(
+            "{} until {:02}:00 - no more work",
+            time_until(t, wake_up),
+            wake_up
+        )
+    } else if h >= bedtime - 2 {
+        format!("{} until bedtime - no more work", time_until(t, bedtime))
+    } else if h >= bedtime - 4 {
+        format!("{} until bedtime", time_until(t, bedtime))
+    } else {
+        // round up to the next half hour
+        let n = (h * 60 + t.minute() as i64 + 29) / 30;
+        format!("{:02}:{:02}", n / 2, n % 2 * 30)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_answer() {
+        let cases = [
+            ("18:30", "18:30"),
+            ("18:31", "19:00"),
+            ("18:59", "19:00"),
+            ("19:00", "4 hours until bedtime"),
+            ("19:01", "3.5 hours until bedtime"),
+            ("20:59", "2 hours until bedtime"),
+            ("21:00", "2 hours until bedtime - no more work"),
+            ("21:01", "1.5 hours until bedtime - no more work"),
+            ("22:00", "1 hour until bedtime - no more work"),
+            ("22:01", "0.5 hours until bedtime - no more work"),
+            ("22:30", "0.5 hours until bedtime - no more work"),
+            ("22:31", "29 minutes until bedtime - no more work"),
+            ("22:59", "1 minute until bedtime - no more work"),
+            ("23:00", "9 hours until 08:00 - no more work"),
+            ("23:01", "8.5 hours until 08:00 - no more work"),
+            ("23:59", "8 hours until 08:00 - no more work"),
+            ("0:00", "8 hours until 08:00 - no more work"),
+            ("2:29", "5.5 hours until 08:00 - no more work"),
+            ("2:42", "5 hours until 08:00 - no more work"),
+            ("4:59", "3 hours until 08:00 - no more work"),
+            ("5:00", "05:00"),
+        ];
+        for (hm, expected) in cases {
+            let t: HourMinute = hm.parse().unwrap();
+            assert_eq!(time_answer(t, 8, 23), expected, "at {}", hm);
+        }
+    }
+}
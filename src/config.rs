@@ -1,68 +1,218 @@
+use crate::challenge::ChallengeKind;
+use crate::manager::{CurrentState, ReasonKind};
 use crate::time::HourMinute;
+use chrono::Weekday;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::fs;
 use std::path::PathBuf;
 use toml_edit::easy as toml;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RequirementConfig {
     pub name: String,
     pub due: HourMinute,
+    pub days: Option<Vec<Weekday>>,
+    pub max_postpones: Option<u32>,
+    pub max_postpone_minutes: Option<i64>,
+    /// makes the requirement recur this many minutes after each completion
+    pub interval_minutes: Option<i64>,
+    /// the latest time at which a recurring requirement can be due
+    pub last_due: Option<HourMinute>,
+    /// whether a recurring requirement is due every `interval_minutes` after `due`, rather than
+    /// after the previous completion
+    pub fixed_schedule: Option<bool>,
+    /// the requirement can't be completed before this time
+    pub available_from: Option<HourMinute>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl RequirementConfig {
+    pub fn is_active_on(&self, weekday: Weekday) -> bool {
+        is_active_on(&self.days, weekday)
+    }
+    /// A requirement that is due before it can be completed would lock the screen with no way
+    /// to unlock it.
+    fn validate(&self) -> Result<(), String> {
+        match self.available_from {
+            Some(available_from) if available_from > self.due => Err(format!(
+                "Requirement '{}' is due at {} but cannot be completed until {}",
+                self.name, self.due, available_from
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LockedTimeRangeConfig {
     pub start: Option<HourMinute>,
     pub end: Option<HourMinute>,
+    pub days: Option<Vec<Weekday>>,
+}
+
+impl LockedTimeRangeConfig {
+    pub fn is_active_on(&self, weekday: Weekday) -> bool {
+        is_active_on(&self.days, weekday)
+    }
+    /// A range whose end is earlier than its start lasts until the end time on the next day.
+    pub fn crosses_midnight(&self) -> bool {
+        matches!((&self.start, &self.end), (Some(start), Some(end)) if end < start)
+    }
+}
+
+/// Entries without a list of days apply to every day of the week.
+fn is_active_on(days: &Option<Vec<Weekday>>, weekday: Weekday) -> bool {
+    match days {
+        Some(days) => days.contains(&weekday),
+        None => true,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeactivationConfig {
+    pub max_minutes: Option<i64>,
+    pub daily_budget_minutes: Option<i64>,
+    pub cooldown_minutes: Option<i64>,
+    pub denied_reasons: Option<Vec<ReasonKind>>,
+}
+
+/// Limits on pausing or extending the work period.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimerQuotaConfig {
+    pub max_minutes: Option<i64>,
+    pub daily_budget_minutes: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChallengesConfig {
+    pub unlock_timer: Option<ChallengeKind>,
+    pub deactivate: Option<ChallengeKind>,
+    pub expiry_seconds: Option<i64>,
+}
+
+/// A command that is run when the state or the reason for it changes. Each list restricts the
+/// transitions that the hook runs on, and an omitted list matches everything.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HookConfig {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub states: Option<Vec<CurrentState>>,
+    pub previous_states: Option<Vec<CurrentState>>,
+    pub reasons: Option<Vec<ReasonKind>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockWarningsConfig {
+    /// how many minutes before a lock to send warnings
+    pub minutes: Vec<i64>,
+    pub command: Option<String>,
+    pub args: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DiagonatorConfig {
-    pub bind_on: String,
+    pub bind_on: Option<String>,
+    pub socket_path: Option<PathBuf>,
+    /// permissions of the socket file, e.g. 0o660 to allow access from the file's group
+    pub socket_mode: Option<u32>,
+    /// where to listen for the line-delimited JSON protocol
+    pub line_bind_on: Option<String>,
+    pub line_socket_path: Option<PathBuf>,
+    /// the lock screen command, which the server runs whenever diagonator should be running
+    pub diagonator_path: Option<String>,
+    pub diagonator_args: Option<Vec<String>>,
     pub requirements: Option<Vec<RequirementConfig>>,
     pub locked_time_ranges: Option<Vec<LockedTimeRangeConfig>>,
     pub work_period_minutes: i64,
     pub break_minutes: i64,
+    pub long_break_minutes: Option<i64>,
+    /// the number of work periods before each long break
+    pub long_break_interval: Option<u32>,
+    pub allow_removing_configured_requirements: Option<bool>,
+    pub deactivation: Option<DeactivationConfig>,
+    /// `PauseTimer` and `ExtendWorkPeriod` are only allowed if these are set
+    pub pause: Option<TimerQuotaConfig>,
+    pub extension: Option<TimerQuotaConfig>,
+    pub challenges: Option<ChallengesConfig>,
+    pub hooks: Option<Vec<HookConfig>>,
+    pub lock_warnings: Option<LockWarningsConfig>,
 }
 
 impl Default for DiagonatorConfig {
     fn default() -> Self {
         Self {
-            bind_on: "0.0.0.0:3000".to_owned(),
+            bind_on: Some("0.0.0.0:3000".to_owned()),
+            socket_path: None,
+            socket_mode: None,
+            // diagonator-ctl uses the line protocol, so it should work out of the box
+            line_bind_on: Some("127.0.0.1:3001".to_owned()),
+            line_socket_path: None,
+            diagonator_path: None,
+            diagonator_args: None,
             requirements: Some(vec![
                 RequirementConfig {
                     name: "Name of requirement 1".to_owned(),
                     due: HourMinute::new(8, 30).unwrap(),
+                    days: None,
+                    max_postpones: None,
+                    max_postpone_minutes: None,
+                    interval_minutes: None,
+                    last_due: None,
+                    fixed_schedule: None,
+                    available_from: None,
                 },
                 RequirementConfig {
                     name: "Name of requirement 2".to_owned(),
                     due: HourMinute::new(20, 00).unwrap(),
+                    days: None,
+                    max_postpones: None,
+                    max_postpone_minutes: None,
+                    interval_minutes: None,
+                    last_due: None,
+                    fixed_schedule: None,
+                    available_from: None,
                 },
             ]),
             locked_time_ranges: Some(vec![
-                LockedTimeRangeConfig {
-                    start: None,
-                    end: Some(HourMinute::new(4, 30).unwrap()),
-                },
                 LockedTimeRangeConfig {
                     start: Some(HourMinute::new(12, 00).unwrap()),
                     end: Some(HourMinute::new(13, 00).unwrap()),
+                    days: None,
                 },
                 LockedTimeRangeConfig {
                     start: Some(HourMinute::new(22, 00).unwrap()),
-                    end: None,
+                    end: Some(HourMinute::new(4, 30).unwrap()),
+                    days: None,
                 },
             ]),
             work_period_minutes: 25,
             break_minutes: 5,
+            long_break_minutes: None,
+            long_break_interval: None,
+            allow_removing_configured_requirements: Some(false),
+            deactivation: None,
+            pause: None,
+            extension: None,
+            challenges: None,
+            hooks: None,
+            lock_warnings: None,
+        }
+    }
+}
+
+impl DiagonatorConfig {
+    fn validate(&self) -> Result<(), String> {
+        for req in self.requirements.iter().flatten() {
+            req.validate()?;
         }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub enum LoadConfigError {
     ConfigDirNotFound,
+    InvalidConfig(String),
     SerializationError(toml::ser::Error),
     DeserializationError(toml::de::Error),
     WriteError(PathBuf, std::io::Error),
@@ -76,6 +226,7 @@ impl Display for LoadConfigError {
             Self::ConfigDirNotFound => {
                 write!(f, "Unable to determine path to configuration directory")
             }
+            Self::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
             Self::SerializationError(err) => {
                 write!(f, "Received error '{}' when serializing configuration", err)
             }
@@ -135,19 +286,32 @@ fn make_default_config(config_file_path: &PathBuf) -> Result<(), LoadConfigError
         .map_err(|err| LoadConfigError::WriteError(config_file_path.clone(), err))
 }
 
-pub fn load_config() -> Result<DiagonatorConfig, LoadConfigError> {
-    let mut config_file_path = dirs::config_dir().ok_or(LoadConfigError::ConfigDirNotFound)?;
-    config_file_path.push("diagonator-server");
-    fs::create_dir_all(&config_file_path)
-        .map_err(|err| LoadConfigError::CreateDirError(config_file_path.clone(), err))?;
+pub fn config_dir() -> Result<PathBuf, LoadConfigError> {
+    let mut config_dir = dirs::config_dir().ok_or(LoadConfigError::ConfigDirNotFound)?;
+    config_dir.push("diagonator-server");
+    fs::create_dir_all(&config_dir)
+        .map_err(|err| LoadConfigError::CreateDirError(config_dir.clone(), err))?;
+    Ok(config_dir)
+}
+
+pub fn config_file_path() -> Result<PathBuf, LoadConfigError> {
+    let mut config_file_path = config_dir()?;
     config_file_path.push("config.toml");
+    Ok(config_file_path)
+}
+
+pub fn read_config(config_file_path: &PathBuf) -> Result<DiagonatorConfig, LoadConfigError> {
+    let contents = fs::read_to_string(config_file_path)
+        .map_err(|err| LoadConfigError::ReadError(config_file_path.clone(), err))?;
+    let config: DiagonatorConfig = toml::from_str(&contents)?;
+    config.validate().map_err(LoadConfigError::InvalidConfig)?;
+    Ok(config)
+}
+
+pub fn load_config(config_file_path: &PathBuf) -> Result<DiagonatorConfig, LoadConfigError> {
     if !config_file_path.exists() {
-        make_default_config(&config_file_path)?;
+        make_default_config(config_file_path)?;
     }
     eprintln!("Loading configuration from {}", config_file_path.display());
-    let contents = fs::read_to_string(&config_file_path)
-        .map_err(|err| LoadConfigError::ReadError(config_file_path, err))?;
-
-    let config = toml::from_str(&contents)?;
-    Ok(config)
+    read_config(config_file_path)
 }
@@ -0,0 +1,165 @@
+use crate::manager::{CurrentInfo, CurrentStateReason};
+use crate::server::Response;
+use crate::time::Timestamp;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum OpenEventLogError {
+    DataDirNotFound,
+    CreateDirError(PathBuf, std::io::Error),
+    DatabaseError(PathBuf, rusqlite::Error),
+}
+
+impl Display for OpenEventLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DataDirNotFound => {
+                write!(f, "Unable to determine path to data directory")
+            }
+            Self::CreateDirError(path, err) => {
+                write!(
+                    f,
+                    "Received error '{}' when creating directory {}",
+                    err,
+                    path.display()
+                )
+            }
+            Self::DatabaseError(path, err) => {
+                write!(
+                    f,
+                    "Received error '{}' when opening event log {}",
+                    err,
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+/// Records state transitions and handled requests in a SQLite database, so that analytics
+/// don't depend on which client made a change.
+pub struct EventLog {
+    conn: Connection,
+}
+
+impl EventLog {
+    pub fn in_data_dir() -> Result<Self, OpenEventLogError> {
+        let mut path = dirs::data_dir().ok_or(OpenEventLogError::DataDirNotFound)?;
+        path.push("diagonator-server");
+        fs::create_dir_all(&path)
+            .map_err(|err| OpenEventLogError::CreateDirError(path.clone(), err))?;
+        path.push("events.sqlite3");
+        eprintln!("Logging events to {}", path.display());
+        Self::open(&path).map_err(|err| OpenEventLogError::DatabaseError(path, err))
+    }
+    fn open(path: &PathBuf) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transition_log(
+                date TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                state TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                reason_details TEXT,
+                until INTEGER,
+                deactivated INTEGER NOT NULL) STRICT;
+            CREATE TABLE IF NOT EXISTS request_log(
+                date TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                type TEXT NOT NULL,
+                request TEXT NOT NULL,
+                success INTEGER NOT NULL,
+                response TEXT NOT NULL,
+                state TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                reason_details TEXT,
+                requirement TEXT) STRICT;",
+        )?;
+        Ok(Self { conn })
+    }
+    pub fn log_transition(&self, current_time: Timestamp, info: &CurrentInfo) {
+        let (date, time) = current_time.get_date_and_seconds();
+        let result = self.conn.execute(
+            "INSERT INTO transition_log(date,time,timestamp,state,reason,reason_details,until,deactivated)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                date,
+                time,
+                current_time.as_secs(),
+                format!("{:?}", info.state()),
+                format!("{:?}", info.reason().kind()),
+                reason_details(info),
+                info.until().map(Timestamp::as_secs),
+                info.deactivated_until().is_some(),
+            ],
+        );
+        if let Err(err) = result {
+            eprintln!("Received error '{}' when logging state transition", err);
+        }
+    }
+    /// Logs a request along with its response. `info` should describe the state of the server
+    /// at the time when the request was received, before it was handled.
+    pub fn log_request<R: Serialize>(
+        &self,
+        current_time: Timestamp,
+        request: &R,
+        response: &Response,
+        info: &CurrentInfo,
+    ) {
+        let (date, time) = current_time.get_date_and_seconds();
+        let request = match serde_json::to_value(request) {
+            Ok(request) => request,
+            Err(err) => {
+                eprintln!("Received error '{}' when serializing request", err);
+                return;
+            }
+        };
+        let request_type = request["type"].as_str().unwrap_or_default().to_owned();
+        // requests that refer to a requirement are logged with its name,
+        // since IDs are only meaningful within a single day
+        let requirement = match (&request["id"], &request["name"]) {
+            (serde_json::Value::Number(id), _) => id
+                .as_u64()
+                .and_then(|id| info.requirement_name(id))
+                .map(str::to_owned),
+            (_, serde_json::Value::String(name)) => Some(name.clone()),
+            _ => None,
+        };
+        let result = self.conn.execute(
+            "INSERT INTO request_log(date,time,timestamp,type,request,success,response,state,reason,reason_details,requirement)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                date,
+                time,
+                current_time.as_secs(),
+                request_type,
+                request.to_string(),
+                !matches!(response, Response::Error { .. }),
+                serde_json::to_string(response).unwrap_or_default(),
+                format!("{:?}", info.state()),
+                format!("{:?}", info.reason().kind()),
+                reason_details(info),
+                requirement,
+            ],
+        );
+        if let Err(err) = result {
+            eprintln!("Received error '{}' when logging request", err);
+        }
+    }
+}
+
+/// Returns the name of the requirement responsible for the current state, if there is one.
+pub fn reason_details(info: &CurrentInfo) -> Option<String> {
+    match info.reason() {
+        CurrentStateReason::RequirementNotMet { id } => {
+            info.requirement_name(*id).map(str::to_owned)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,99 @@
+use crate::config::HookConfig;
+use crate::events::reason_details;
+use crate::manager::CurrentInfo;
+use crate::time::Timestamp;
+use crate::warnings::{LockWarning, LockWarningPolicy};
+use tokio::process::Command;
+
+impl HookConfig {
+    fn matches(&self, previous: &CurrentInfo, info: &CurrentInfo) -> bool {
+        self.states
+            .as_ref()
+            .is_none_or(|states| states.contains(&info.state()))
+            && self
+                .previous_states
+                .as_ref()
+                .is_none_or(|states| states.contains(&previous.state()))
+            && self
+                .reasons
+                .as_ref()
+                .is_none_or(|reasons| reasons.contains(&info.reason().kind()))
+    }
+}
+
+/// Runs the hooks that match a change from `previous` to `info`. The details of the change are
+/// passed to each command in environment variables.
+pub fn run_hooks(
+    hooks: &[HookConfig],
+    current_time: Timestamp,
+    previous: &CurrentInfo,
+    info: &CurrentInfo,
+) {
+    if info.state() == previous.state() && info.reason() == previous.reason() {
+        return;
+    }
+    for hook in hooks.iter().filter(|hook| hook.matches(previous, info)) {
+        let mut command = Command::new(&hook.command);
+        command
+            .args(hook.args.as_deref().unwrap_or_default())
+            .env("DIAGONATOR_STATE", format!("{:?}", info.state()))
+            .env(
+                "DIAGONATOR_PREVIOUS_STATE",
+                format!("{:?}", previous.state()),
+            )
+            .env("DIAGONATOR_REASON", format!("{:?}", info.reason().kind()))
+            .env(
+                "DIAGONATOR_PREVIOUS_REASON",
+                format!("{:?}", previous.reason().kind()),
+            )
+            .env("DIAGONATOR_TIMESTAMP", current_time.as_secs().to_string());
+        if let Some(details) = reason_details(info) {
+            command.env("DIAGONATOR_REASON_DETAILS", details);
+        }
+        if let Some(until) = info.until() {
+            command.env("DIAGONATOR_UNTIL", until.as_secs().to_string());
+        }
+        spawn_hook(command, &hook.command);
+    }
+}
+
+/// Runs the command for a lock warning, if one is configured.
+pub fn run_lock_warning_hook(policy: &LockWarningPolicy, warning: &LockWarning) {
+    let Some(name) = &policy.command else {
+        return;
+    };
+    let mut command = Command::new(name);
+    command
+        .args(&policy.args)
+        .env("DIAGONATOR_REASON", format!("{:?}", warning.reason.kind()))
+        .env("DIAGONATOR_UNTIL", warning.locks_at.as_secs().to_string())
+        .env(
+            "DIAGONATOR_WARNING_SECONDS",
+            warning.threshold.as_secs().to_string(),
+        );
+    if let Some(requirement) = &warning.requirement {
+        command.env("DIAGONATOR_REASON_DETAILS", requirement);
+    }
+    spawn_hook(command, name);
+}
+
+/// Starts a hook command without waiting for it, logging failures.
+fn spawn_hook(mut command: Command, name: &str) {
+    match command.spawn() {
+        Ok(mut child) => {
+            let name = name.to_owned();
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if !status.success() => {
+                        eprintln!("Hook {} exited with {}", name, status)
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        eprintln!("Received error '{}' when waiting for hook {}", err, name)
+                    }
+                }
+            });
+        }
+        Err(err) => eprintln!("Received error '{}' when running hook {}", err, name),
+    }
+}
@@ -0,0 +1,17 @@
+pub mod auth;
+pub mod challenge;
+pub mod config;
+pub mod events;
+pub mod hooks;
+pub mod line_protocol;
+pub mod manager;
+pub mod policy;
+pub mod push;
+pub mod server;
+pub mod simulator;
+pub mod state;
+pub mod supervisor;
+pub mod time;
+#[cfg(unix)]
+pub mod unix_socket;
+pub mod warnings;
@@ -0,0 +1,154 @@
+use crate::auth::{Access, Tokens};
+use crate::manager::{CurrentInfo, DiagonatorManager};
+use crate::server::{authorize, process_request, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::watch;
+
+/// Requests that affect the connection rather than the manager.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ConnectionRequest {
+    Authenticate { token: String },
+    Subscribe,
+    Unsubscribe,
+}
+
+/// Messages that are sent without being requested.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum Event {
+    /// `version` increases every time that the info changes
+    InfoUpdate { version: u64, info: CurrentInfo },
+}
+
+/// Everything that a connection needs in order to handle requests.
+#[derive(Clone)]
+pub struct LineServer {
+    pub manager: &'static Mutex<DiagonatorManager>,
+    pub tokens: &'static Tokens,
+    pub info_updates: watch::Receiver<(CurrentInfo, u64)>,
+}
+
+impl LineServer {
+    pub async fn serve_tcp(self, bind_on: &str) {
+        eprintln!("Line protocol server is listening on {}", bind_on);
+        let listener = tokio::net::TcpListener::bind(bind_on).await.unwrap();
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(self.clone().handle_connection(stream, None));
+                }
+                Err(err) => eprintln!("Received error '{}' when accepting connection", err),
+            }
+        }
+    }
+    #[cfg(unix)]
+    pub async fn serve_unix(self, path: &std::path::Path, mode: u32) {
+        let listener = crate::unix_socket::bind(path, mode);
+        eprintln!("Line protocol server is listening on {}", path.display());
+        loop {
+            match listener.accept().await {
+                // the socket's permissions already restrict who can connect
+                Ok((stream, _)) => {
+                    tokio::spawn(
+                        self.clone()
+                            .handle_connection(stream, Some(Access::Control)),
+                    );
+                }
+                Err(err) => eprintln!("Received error '{}' when accepting connection", err),
+            }
+        }
+    }
+    async fn handle_connection<S: AsyncRead + AsyncWrite>(
+        mut self,
+        stream: S,
+        mut access: Option<Access>,
+    ) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        let mut subscribed = false;
+        loop {
+            let message = tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) if line.trim().is_empty() => continue,
+                    Ok(Some(line)) => {
+                        let was_subscribed = subscribed;
+                        let response = self.handle_line(&line, &mut access, &mut subscribed);
+                        let mut message = serde_json::to_string(&response).unwrap();
+                        if subscribed && !was_subscribed {
+                            // send the current info right away, like Socket.IO clients get
+                            // when they connect
+                            let (info, version) = self.info_updates.borrow_and_update().clone();
+                            message.push('\n');
+                            message += &serde_json::to_string(&Event::InfoUpdate { version, info })
+                                .unwrap();
+                        }
+                        message
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("Received error '{}' when reading from connection", err);
+                        break;
+                    }
+                },
+                changed = self.info_updates.changed(), if subscribed => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let (info, version) = self.info_updates.borrow_and_update().clone();
+                    serde_json::to_string(&Event::InfoUpdate { version, info }).unwrap()
+                }
+            };
+            if writer.write_all((message + "\n").as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+    fn handle_line(
+        &self,
+        line: &str,
+        access: &mut Option<Access>,
+        subscribed: &mut bool,
+    ) -> Response {
+        if let Ok(request) = serde_json::from_str::<ConnectionRequest>(line) {
+            return match request {
+                ConnectionRequest::Authenticate { token } => match self.tokens.access(&token) {
+                    Some(new_access) => {
+                        *access = Some(new_access);
+                        Response::Success
+                    }
+                    None => Response::Error {
+                        msg: "Invalid token.".to_owned(),
+                        rule: None,
+                    },
+                },
+                ConnectionRequest::Subscribe => {
+                    if access.is_none() {
+                        return Response::Error {
+                            msg: "Authenticate before subscribing.".to_owned(),
+                            rule: None,
+                        };
+                    }
+                    *subscribed = true;
+                    Response::Success
+                }
+                ConnectionRequest::Unsubscribe => {
+                    *subscribed = false;
+                    Response::Success
+                }
+            };
+        }
+        match serde_json::from_str::<Request>(line) {
+            Ok(request) => match authorize(*access, &request) {
+                Ok(()) => process_request(self.manager, &request),
+                Err((_, msg)) => Response::Error { msg, rule: None },
+            },
+            Err(err) => Response::Error {
+                msg: format!("Failed to parse request: {}", err),
+                rule: None,
+            },
+        }
+    }
+}
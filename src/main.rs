@@ -1,18 +1,30 @@
-mod config;
-mod manager;
-mod server;
-mod simulator;
-mod time;
-
-use config::load_config;
-use server::launch_server;
+use diagonator_server::auth::Tokens;
+use diagonator_server::config::{config_file_path, load_config};
+use diagonator_server::events::EventLog;
+use diagonator_server::server::launch_server;
+use diagonator_server::state::StateFile;
 
 #[tokio::main]
 async fn main() {
-    match load_config() {
-        Ok(config) => {
-            launch_server(config).await;
-        }
+    let loaded = config_file_path().and_then(|config_file_path| {
+        let config = load_config(&config_file_path)?;
+        Ok((
+            config_file_path,
+            config,
+            StateFile::in_config_dir()?,
+            Tokens::in_config_dir()?,
+        ))
+    });
+    match loaded {
+        Ok((config_file_path, config, state_file, tokens)) => match EventLog::in_data_dir() {
+            Ok(event_log) => {
+                launch_server(config, config_file_path, state_file, event_log, tokens).await;
+            }
+            Err(err) => {
+                eprintln!("Encountered error when opening event log: {}", err);
+                std::process::exit(1);
+            }
+        },
         Err(err) => {
             eprintln!("Encountered error when loading config: {}", err);
             std::process::exit(1);
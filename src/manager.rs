@@ -1,15 +1,101 @@
-use crate::config::{LockedTimeRangeConfig, RequirementConfig};
+use crate::challenge::{ChallengeAction, ChallengeAnswer, ChallengePolicy, Challenges};
+use crate::config::{HookConfig, LockedTimeRangeConfig, RequirementConfig};
+use crate::events::EventLog;
+use crate::hooks::{run_hooks, run_lock_warning_hook};
+use crate::policy::{Deactivation, DeactivationPolicy, TimerQuota, TimerQuotaPolicy};
 use crate::server::Response;
 use crate::simulator::{Simulator, StateChange, StateChangeKind};
+use crate::state::StateFile;
+use crate::supervisor::OverlayStatus;
 use crate::time::{Duration, HourMinute, LocalDate, Timestamp};
+use crate::warnings::{LockWarning, LockWarningPolicy, LockWarnings};
+use chrono::{Datelike, NaiveDate, TimeZone};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-struct Requirement {
+pub struct Requirement {
     id: u64,
     name: String,
     due: Timestamp,
     complete: bool,
+    from_config: bool,
+    /// `None` means that the requirement can be postponed any number of times
+    max_postpones: Option<u32>,
+    /// `None` means that there is no limit on the total postponement
+    max_postpone_duration: Option<Duration>,
+    postponements: Vec<Postponement>,
+    /// `None` if the requirement doesn't recur
+    interval: Option<RequirementInterval>,
+    /// the requirement can't be completed before this time
+    available_from: Option<Timestamp>,
+    /// whether the requirement can be completed at the time when the info was created
+    #[serde(default)]
+    completable: bool,
+}
+
+impl Requirement {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn due(&self) -> Timestamp {
+        self.due
+    }
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+    pub fn available_from(&self) -> Option<Timestamp> {
+        self.available_from
+    }
+    pub fn completable(&self) -> bool {
+        self.completable
+    }
+    fn total_postponement(&self) -> Duration {
+        self.postponements.iter().map(|p| p.duration).sum()
+    }
+    /// Checks that the requirement could still be completed if it were due at `due`.
+    fn check_due(&self, due: Timestamp) -> Result<(), String> {
+        match self.available_from {
+            Some(available_from) if due < available_from => Err(format!(
+                "Requirement {} cannot be due before {}, when it can be completed.",
+                self.id,
+                available_from.format_hm()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Describes when the next instance of a recurring requirement is due.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct RequirementInterval {
+    every: Duration,
+    /// the due time of the first instance, which the fixed schedule is based on
+    first_due: Timestamp,
+    /// no instances are due after this time
+    last_due: Timestamp,
+    /// whether instances are due on a fixed schedule rather than `every` after the previous
+    /// instance was completed
+    fixed_schedule: bool,
+}
+
+impl RequirementInterval {
+    fn next_due(&self, completion_time: Timestamp) -> Timestamp {
+        if !self.fixed_schedule {
+            return completion_time + self.every;
+        }
+        let every = self.every.as_secs();
+        let elapsed = (completion_time - self.first_due).as_secs().max(0);
+        self.first_due + Duration::from_seconds((elapsed / every + 1) * every)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Postponement {
+    time: Timestamp,
+    duration: Duration,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -17,28 +103,86 @@ struct TimeRange {
     id: u64,
     start: Option<Timestamp>,
     end: Option<Timestamp>,
+    from_config: bool,
+}
+
+impl TimeRange {
+    /// Creates the instance of `ltr` that starts on `date`.
+    fn starting_on(
+        id: u64,
+        date: LocalDate,
+        ltr: &LockedTimeRangeConfig,
+        from_config: bool,
+    ) -> Self {
+        let end_date = if ltr.crosses_midnight() {
+            date.succ()
+        } else {
+            date
+        };
+        Self {
+            id,
+            start: Timestamp::from_date_hm_opt(&date, &ltr.start),
+            end: Timestamp::from_date_hm_opt(&end_date, &ltr.end),
+            from_config,
+        }
+    }
+    fn is_active(&self, current_time: Timestamp) -> bool {
+        self.start.is_none_or(|start| start <= current_time)
+            && self.end.is_none_or(|end| current_time < end)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(tag = "type")]
 enum BreakTimer {
-    Unlocked { until: Timestamp },
-    Locked { until: Timestamp },
+    Unlocked {
+        until: Timestamp,
+    },
+    Locked {
+        until: Timestamp,
+    },
     Unlockable,
+    /// the work period is paused because the user has been idle since `since`, which the
+    /// server was told at `reported_at`
+    Idle {
+        since: Timestamp,
+        reported_at: Timestamp,
+        remaining: Duration,
+    },
+    /// the work period was paused with `PauseTimer`, and resumes automatically at `until`
+    Paused {
+        since: Timestamp,
+        until: Option<Timestamp>,
+        remaining: Duration,
+    },
 }
 
 struct BreakTimerManager {
     timer: BreakTimer,
     work_period_duration: Duration,
     break_duration: Duration,
+    /// `None` if every break has the same length
+    long_break_duration: Option<Duration>,
+    /// the number of work periods before each long break
+    long_break_interval: u32,
+    /// the number of work periods that ended today
+    completed_cycles: u32,
+    /// time spent paused today, not including the current pause
+    paused_today: Duration,
+    extended_today: Duration,
 }
 
 impl BreakTimerManager {
-    fn new(work_period_duration: Duration, break_duration: Duration) -> Self {
+    fn new(config: &DiagonatorManagerConfig) -> Self {
         Self {
             timer: BreakTimer::Unlockable,
-            work_period_duration,
-            break_duration,
+            work_period_duration: config.work_period_duration,
+            break_duration: config.break_duration,
+            long_break_duration: config.long_break_duration,
+            long_break_interval: config.long_break_interval,
+            completed_cycles: 0,
+            paused_today: Duration::ZERO,
+            extended_today: Duration::ZERO,
         }
     }
     fn unlock(&mut self, current_time: Timestamp) -> Result<(), String> {
@@ -51,29 +195,153 @@ impl BreakTimerManager {
                 Ok(())
             }
             BreakTimer::Locked { until: _ } => Err("Break timer is locked.".to_owned()),
-            BreakTimer::Unlocked { until: _ } => Err("Break timer is already unlocked.".to_owned()),
+            BreakTimer::Unlocked { until: _ }
+            | BreakTimer::Idle { .. }
+            | BreakTimer::Paused { .. } => Err("Break timer is already unlocked.".to_owned()),
         }
     }
     fn lock(&mut self, current_time: Timestamp) -> Result<(), String> {
         self.refresh(current_time);
         match self.timer {
-            BreakTimer::Unlocked { until: _ } => {
-                self.timer = BreakTimer::Locked {
-                    until: current_time + self.break_duration,
+            BreakTimer::Unlocked { until: _ } | BreakTimer::Idle { .. } => {
+                self.start_break(current_time);
+                Ok(())
+            }
+            BreakTimer::Paused { since, .. } => {
+                self.paused_today = self.paused_today + (current_time - since);
+                self.start_break(current_time);
+                Ok(())
+            }
+            _ => Err("Break timer is not unlocked.".to_owned()),
+        }
+    }
+    /// Ends the current work period, which completes a cycle.
+    fn start_break(&mut self, start: Timestamp) {
+        let duration = self.next_break_duration();
+        self.completed_cycles += 1;
+        self.timer = BreakTimer::Locked {
+            until: start + duration,
+        };
+    }
+    /// Returns the length of the break at the end of the current work period.
+    fn next_break_duration(&self) -> Duration {
+        match self.long_break_duration {
+            Some(long_break_duration)
+                if (self.completed_cycles + 1).is_multiple_of(self.long_break_interval) =>
+            {
+                long_break_duration
+            }
+            _ => self.break_duration,
+        }
+    }
+    /// Pauses the work period because the user stopped being active at `since`. `since` comes
+    /// from the client, so it is only reported in `CurrentInfo`, and the work period is paused
+    /// from when the server found out instead.
+    fn report_idle(&mut self, current_time: Timestamp, since: Timestamp) {
+        self.refresh(current_time);
+        if let BreakTimer::Unlocked { until } = self.timer {
+            let since = since
+                .max(until - self.work_period_duration)
+                .min(current_time);
+            self.timer = BreakTimer::Idle {
+                since,
+                reported_at: current_time,
+                remaining: until - current_time,
+            };
+        }
+    }
+    /// Resumes the work period when the user becomes active again.
+    fn report_activity(&mut self, current_time: Timestamp) {
+        self.refresh(current_time);
+        if let BreakTimer::Idle { remaining, .. } = self.timer {
+            self.timer = BreakTimer::Unlocked {
+                until: current_time + remaining,
+            };
+        }
+    }
+    /// Pauses the work period until `until`, or until it is resumed.
+    fn pause(&mut self, current_time: Timestamp, until: Option<Timestamp>) -> Result<(), String> {
+        self.refresh(current_time);
+        match self.timer {
+            BreakTimer::Unlocked { until: end } => {
+                self.timer = BreakTimer::Paused {
+                    since: current_time,
+                    until,
+                    remaining: end - current_time,
+                };
+                Ok(())
+            }
+            BreakTimer::Paused { .. } => Err("Break timer is already paused.".to_owned()),
+            _ => Err("Break timer is not unlocked.".to_owned()),
+        }
+    }
+    fn resume(&mut self, current_time: Timestamp) -> Result<(), String> {
+        self.refresh(current_time);
+        match self.timer {
+            BreakTimer::Paused {
+                since, remaining, ..
+            } => {
+                self.paused_today = self.paused_today + (current_time - since);
+                self.timer = BreakTimer::Unlocked {
+                    until: current_time + remaining,
+                };
+                Ok(())
+            }
+            _ => Err("Break timer is not paused.".to_owned()),
+        }
+    }
+    fn extend(&mut self, current_time: Timestamp, duration: Duration) -> Result<(), String> {
+        self.refresh(current_time);
+        match self.timer {
+            BreakTimer::Unlocked { until } => {
+                self.timer = BreakTimer::Unlocked {
+                    until: until + duration,
                 };
+                self.extended_today = self.extended_today + duration;
                 Ok(())
             }
             _ => Err("Break timer is not unlocked.".to_owned()),
         }
     }
+    /// Returns the time spent paused today, including the current pause.
+    fn pause_used(&self, current_time: Timestamp) -> Duration {
+        match self.timer {
+            BreakTimer::Paused { since, .. } => self.paused_today + (current_time - since),
+            _ => self.paused_today,
+        }
+    }
+    /// Returns the number of work periods left until the next long break, including the
+    /// current one.
+    fn cycles_until_long_break(&self) -> Option<u32> {
+        self.long_break_duration?;
+        Some(self.long_break_interval - self.completed_cycles % self.long_break_interval)
+    }
     fn refresh(&mut self, current_time: Timestamp) {
-        if let BreakTimer::Unlocked { until } = self.timer {
+        if let BreakTimer::Paused {
+            since,
+            until: Some(until),
+            remaining,
+        } = self.timer
+        {
             if current_time >= until {
-                self.timer = BreakTimer::Locked {
-                    until: until + self.break_duration,
+                self.paused_today = self.paused_today + (until - since);
+                self.timer = BreakTimer::Unlocked {
+                    until: until + remaining,
                 };
             }
         }
+        if let BreakTimer::Unlocked { until } = self.timer {
+            if current_time >= until {
+                self.start_break(until);
+            }
+        }
+        // being idle for as long as a break, from when the server found out about it, counts
+        // as taking the break, even if the user never reports being active again
+        if let BreakTimer::Idle { reported_at, .. } = self.timer {
+            if current_time >= reported_at + self.break_duration {
+                self.start_break(reported_at);
+            }
+        }
         if let BreakTimer::Locked { until } = self.timer {
             if current_time >= until {
                 self.timer = BreakTimer::Unlockable;
@@ -98,21 +366,102 @@ pub enum CurrentStateReason {
     NoConstraints,
 }
 
+/// The variants of `CurrentStateReason` without their data, for use in the configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReasonKind {
+    BreakTimer,
+    RequirementNotMet,
+    LockedTimeRange,
+    NoConstraints,
+}
+
+impl CurrentStateReason {
+    pub fn kind(&self) -> ReasonKind {
+        match self {
+            Self::BreakTimer => ReasonKind::BreakTimer,
+            Self::RequirementNotMet { .. } => ReasonKind::RequirementNotMet,
+            Self::LockedTimeRange { .. } => ReasonKind::LockedTimeRange,
+            Self::NoConstraints => ReasonKind::NoConstraints,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CurrentInfo {
     state: CurrentState,
     until: Option<Timestamp>,
+    /// the state after `until`
+    next_state: Option<CurrentState>,
     reason: CurrentStateReason,
     locked_time_ranges: Vec<TimeRange>,
     requirements: Vec<Requirement>,
+    upcoming_locked_time_ranges: Vec<TimeRange>,
+    upcoming_requirements: Vec<Requirement>,
     deactivated_until: Option<Timestamp>,
     diagonator_running: bool,
+    /// when the user became idle, if the work period is paused because of it
+    idle_since: Option<Timestamp>,
+    /// the number of work periods that ended today
+    completed_cycles: u32,
+    /// `None` if long breaks aren't configured
+    cycles_until_long_break: Option<u32>,
+    /// when the work period was paused with `PauseTimer`, if it is paused
+    paused_since: Option<Timestamp>,
+    /// `None` if pausing isn't allowed. The current pause isn't counted until it ends.
+    pause_quota: Option<TimerQuota>,
+    /// `None` if extending the work period isn't allowed
+    extension_quota: Option<TimerQuota>,
+    /// `None` if the server isn't configured to run the lock screen itself
+    overlay: Option<OverlayStatus>,
 }
+
+impl CurrentInfo {
+    pub fn state(&self) -> CurrentState {
+        self.state
+    }
+    pub fn until(&self) -> Option<Timestamp> {
+        self.until
+    }
+    pub fn next_state(&self) -> Option<CurrentState> {
+        self.next_state
+    }
+    pub fn reason(&self) -> &CurrentStateReason {
+        &self.reason
+    }
+    pub fn deactivated_until(&self) -> Option<Timestamp> {
+        self.deactivated_until
+    }
+    pub fn diagonator_running(&self) -> bool {
+        self.diagonator_running
+    }
+    pub fn requirements(&self) -> &[Requirement] {
+        &self.requirements
+    }
+    pub fn requirement_name(&self, id: u64) -> Option<&str> {
+        self.requirements
+            .iter()
+            .chain(&self.upcoming_requirements)
+            .find(|req| req.id == id)
+            .map(|req| req.name.as_str())
+    }
+    fn is_transition_from(&self, previous: &CurrentInfo) -> bool {
+        self.state != previous.state
+            || self.reason != previous.reason
+            || self.diagonator_running != previous.diagonator_running
+    }
+}
+
 struct Constraints {
     break_timer: BreakTimerManager,
     requirements: Vec<Requirement>,
     locked_time_ranges: Vec<TimeRange>,
+    /// Requirements and locked time ranges for the next day, which are included in the
+    /// simulation so that state changes at or after midnight are predicted correctly.
+    upcoming_requirements: Vec<Requirement>,
+    upcoming_locked_time_ranges: Vec<TimeRange>,
     deactivated_until: Option<Timestamp>,
+    /// deactivations that happened today, used to enforce the deactivation policy
+    deactivations: Vec<Deactivation>,
 }
 
 impl Constraints {
@@ -123,6 +472,53 @@ impl Constraints {
                 self.deactivated_until = None;
             }
         }
+        let result = self.build_simulator(current_time).run(current_time);
+        let diagonator_running = !(matches!(result.target_state, CurrentState::Unlocked)
+            || self.deactivated_until.is_some());
+        CurrentInfo {
+            state: result.target_state,
+            until: result.until,
+            next_state: result.next_state,
+            reason: result.reason,
+            locked_time_ranges: self.locked_time_ranges.clone(),
+            requirements: self
+                .requirements
+                .iter()
+                .map(|req| Requirement {
+                    completable: !req.complete
+                        && req
+                            .available_from
+                            .is_none_or(|available_from| available_from <= current_time),
+                    ..req.clone()
+                })
+                .collect(),
+            upcoming_locked_time_ranges: self.upcoming_locked_time_ranges.clone(),
+            upcoming_requirements: self.upcoming_requirements.clone(),
+            deactivated_until: self.deactivated_until,
+            diagonator_running,
+            idle_since: match self.break_timer.timer {
+                BreakTimer::Idle { since, .. } => Some(since),
+                _ => None,
+            },
+            completed_cycles: self.break_timer.completed_cycles,
+            cycles_until_long_break: self.break_timer.cycles_until_long_break(),
+            paused_since: match self.break_timer.timer {
+                BreakTimer::Paused { since, .. } => Some(since),
+                _ => None,
+            },
+            pause_quota: None,
+            extension_quota: None,
+            overlay: None,
+        }
+    }
+    /// Returns every kind of constraint that applies between `current_time` and `end`, even if
+    /// it isn't the reason for the state because another constraint has a higher priority.
+    fn reasons_until(&self, current_time: Timestamp, end: Timestamp) -> Vec<ReasonKind> {
+        self.build_simulator(current_time)
+            .constraints_between(current_time, end)
+    }
+    fn build_simulator(&self, current_time: Timestamp) -> Simulator {
+        let next_day_start = Timestamp::start_of_day(&current_time.get_date().succ());
         let mut simulator = Simulator::new();
         // now we push the state changes into the simulator in the following order:
         // 1. requirements
@@ -131,7 +527,7 @@ impl Constraints {
         // this ensures that if multiple state changes occur at the same time,
         // requirements and locked time ranges will get first and second priority,
         // respectively, when determining the reason
-        for requirement in &self.requirements {
+        for requirement in self.requirements.iter().chain(&self.upcoming_requirements) {
             if !requirement.complete {
                 simulator.push(StateChange {
                     kind: StateChangeKind::RequirementLocked(requirement.id),
@@ -139,11 +535,23 @@ impl Constraints {
                 })
             }
         }
+        // today's ranges without an end last until midnight, which is also
+        // when the next day's ranges without a start begin
         for ltr in &self.locked_time_ranges {
             simulator.push(StateChange {
                 kind: StateChangeKind::RangeLocked(ltr.id),
                 time: ltr.start.unwrap_or(Timestamp::ZERO),
             });
+            simulator.push(StateChange {
+                kind: StateChangeKind::RangeUnlocked(ltr.id),
+                time: ltr.end.unwrap_or(next_day_start),
+            });
+        }
+        for ltr in &self.upcoming_locked_time_ranges {
+            simulator.push(StateChange {
+                kind: StateChangeKind::RangeLocked(ltr.id),
+                time: ltr.start.unwrap_or(next_day_start),
+            });
             if let Some(ltr_end) = ltr.end {
                 simulator.push(StateChange {
                     kind: StateChangeKind::RangeUnlocked(ltr.id),
@@ -166,27 +574,44 @@ impl Constraints {
                     time: *until,
                 });
             }
+            BreakTimer::Idle { reported_at, .. } => {
+                simulator.push(StateChange {
+                    kind: StateChangeKind::BreakTimerLocked,
+                    time: *reported_at + self.break_timer.break_duration,
+                });
+                simulator.push(StateChange {
+                    kind: StateChangeKind::BreakTimerUnlockable,
+                    time: *reported_at + self.break_timer.next_break_duration(),
+                });
+            }
+            BreakTimer::Paused { until: None, .. } => {}
+            BreakTimer::Paused {
+                until: Some(until),
+                remaining,
+                ..
+            } => simulator.push(StateChange {
+                kind: StateChangeKind::BreakTimerLocked,
+                time: *until + *remaining,
+            }),
             BreakTimer::Unlockable => simulator.push(StateChange {
                 kind: StateChangeKind::BreakTimerUnlockable,
                 time: Timestamp::ZERO,
             }),
         }
-        let result = simulator.run(current_time);
-        let diagonator_running = !(matches!(result.target_state, CurrentState::Unlocked)
-            || self.deactivated_until.is_some());
-        CurrentInfo {
-            state: result.target_state,
-            until: result.until,
-            reason: result.reason,
-            locked_time_ranges: self.locked_time_ranges.clone(),
-            requirements: self.requirements.clone(),
-            deactivated_until: self.deactivated_until,
-            diagonator_running,
-        }
+        simulator
     }
-    fn complete_requirement(&mut self, id: u64) -> Result<(), String> {
+    fn complete_requirement(&mut self, current_time: Timestamp, id: u64) -> Result<(), String> {
         for req in &mut self.requirements {
             if req.id == id {
+                if let Some(available_from) = req.available_from {
+                    if current_time < available_from {
+                        return Err(format!(
+                            "Requirement {} cannot be completed until {}.",
+                            id,
+                            available_from.format_hm()
+                        ));
+                    }
+                }
                 if !req.complete {
                     req.complete = true;
                     return Ok(());
@@ -197,6 +622,144 @@ impl Constraints {
         }
         Err(format!("Requirement {} not found.", id))
     }
+    /// Ends the current deactivation early, so that only the time that was actually
+    /// used counts towards the daily budget.
+    fn end_deactivation(&mut self, current_time: Timestamp) {
+        if let (Some(du), Some(last)) =
+            (self.deactivated_until.take(), self.deactivations.last_mut())
+        {
+            if du > current_time && last.start + last.duration == du {
+                last.duration = current_time - last.start;
+            }
+        }
+    }
+    fn remove_locked_time_range(&mut self, current_time: Timestamp, id: u64) -> Result<(), String> {
+        let index = self
+            .locked_time_ranges
+            .iter()
+            .position(|ltr| ltr.id == id)
+            .ok_or_else(|| format!("Locked time range {} not found.", id))?;
+        let ltr = &self.locked_time_ranges[index];
+        if ltr.from_config {
+            return Err(format!(
+                "Locked time range {} comes from the configuration file and cannot be removed.",
+                id
+            ));
+        }
+        if ltr.is_active(current_time) {
+            return Err(format!(
+                "Locked time range {} has already started and cannot be removed.",
+                id
+            ));
+        }
+        self.locked_time_ranges.remove(index);
+        Ok(())
+    }
+    fn remove_requirement(&mut self, id: u64, allow_configured: bool) -> Result<(), String> {
+        let index = self
+            .requirements
+            .iter()
+            .position(|req| req.id == id)
+            .ok_or_else(|| format!("Requirement {} not found.", id))?;
+        if self.requirements[index].from_config && !allow_configured {
+            return Err(format!(
+                "Requirement {} comes from the configuration file and cannot be removed.",
+                id
+            ));
+        }
+        self.requirements.remove(index);
+        Ok(())
+    }
+    fn update_requirement(
+        &mut self,
+        id: u64,
+        name: Option<String>,
+        due: Option<Timestamp>,
+        allow_configured: bool,
+    ) -> Result<(), String> {
+        let req = self
+            .requirements
+            .iter_mut()
+            .find(|req| req.id == id)
+            .ok_or_else(|| format!("Requirement {} not found.", id))?;
+        if let Some(due) = due {
+            // moving a configured requirement's deadline forward is always allowed,
+            // since it only makes the requirement stricter
+            if req.from_config && due > req.due && !allow_configured {
+                return Err(format!(
+                    "Requirement {} comes from the configuration file and its deadline cannot be moved back.",
+                    id
+                ));
+            }
+            req.check_due(due)?;
+            req.due = due;
+        }
+        if let Some(name) = name {
+            req.name = name;
+        }
+        Ok(())
+    }
+    fn postpone_requirement(
+        &mut self,
+        current_time: Timestamp,
+        id: u64,
+        duration: Duration,
+    ) -> Result<(), String> {
+        if duration <= Duration::ZERO {
+            return Err("Postponement duration must be positive.".to_owned());
+        }
+        let req = self
+            .requirements
+            .iter_mut()
+            .find(|req| req.id == id)
+            .ok_or_else(|| format!("Requirement {} not found.", id))?;
+        if req.complete {
+            return Err(format!("Requirement {} has already been completed.", id));
+        }
+        if req.max_postpones == Some(0) {
+            return Err(format!("Requirement {} cannot be postponed.", id));
+        }
+        if let Some(max_postpones) = req.max_postpones {
+            if req.postponements.len() >= max_postpones as usize {
+                return Err(format!(
+                    "Requirement {} cannot be postponed more than {} times.",
+                    id, max_postpones
+                ));
+            }
+        }
+        if let Some(max_duration) = req.max_postpone_duration {
+            if req.total_postponement() + duration > max_duration {
+                return Err(format!(
+                    "Requirement {} cannot be postponed by more than {} in total.",
+                    id, max_duration
+                ));
+            }
+        }
+        req.check_due(req.due + duration)?;
+        req.due = req.due + duration;
+        req.postponements.push(Postponement {
+            time: current_time,
+            duration,
+        });
+        Ok(())
+    }
+}
+
+/// Everything needed to resume the current day after the server restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManagerSnapshot {
+    date: NaiveDate,
+    timer: BreakTimer,
+    completed_cycles: u32,
+    paused_today: Duration,
+    extended_today: Duration,
+    requirements: Vec<Requirement>,
+    locked_time_ranges: Vec<TimeRange>,
+    upcoming_requirements: Vec<Requirement>,
+    upcoming_locked_time_ranges: Vec<TimeRange>,
+    deactivated_until: Option<Timestamp>,
+    deactivations: Vec<Deactivation>,
+    last_id: u64,
 }
 
 pub struct DiagonatorManager {
@@ -204,45 +767,178 @@ pub struct DiagonatorManager {
     cached_info: CurrentInfo,
     cache_time: Timestamp,
     cache_version: u64,
+    state_file: StateFile,
+    saved_snapshot: Option<ManagerSnapshot>,
+    event_log: EventLog,
+    challenges: Challenges,
+    overlay: Option<OverlayStatus>,
+    lock_warnings: LockWarnings,
+    /// warnings that haven't been sent to clients yet
+    pending_lock_warnings: Vec<LockWarning>,
 }
 
 impl DiagonatorManager {
     pub const NO_CACHE: u64 = 0;
-    pub fn new(config: DiagonatorManagerConfig, current_time: Timestamp) -> Self {
+    pub fn new(
+        config: DiagonatorManagerConfig,
+        current_time: Timestamp,
+        state_file: StateFile,
+        event_log: EventLog,
+    ) -> Self {
         let mut manager = DiagonatorManagerInner::new(config);
+        let saved_snapshot = state_file.load();
+        if let Some(snapshot) = &saved_snapshot {
+            if snapshot.date == current_time.get_date().naive_local() {
+                eprintln!("Restoring saved state from {}", snapshot.date);
+                manager.restore(snapshot.clone());
+            } else {
+                eprintln!(
+                    "Saved state is from {}, starting a new day instead",
+                    snapshot.date
+                );
+            }
+        }
         let cached_info = manager.refresh(current_time);
-        Self {
+        let mut manager = Self {
             manager,
             cached_info,
             cache_time: current_time,
             cache_version: Self::NO_CACHE + 1,
-        }
+            state_file,
+            saved_snapshot,
+            event_log,
+            challenges: Challenges::new(),
+            overlay: None,
+            lock_warnings: LockWarnings::default(),
+            pending_lock_warnings: Vec::new(),
+        };
+        manager
+            .event_log
+            .log_transition(current_time, &manager.cached_info);
+        manager.save_state();
+        manager
     }
-    pub fn unlock_timer(&mut self, current_time: Timestamp) -> Response {
+    pub fn unlock_timer(
+        &mut self,
+        current_time: Timestamp,
+        challenge: Option<&ChallengeAnswer>,
+    ) -> Response {
         let info = self.refresh_cache(current_time);
         if matches!(info.state, CurrentState::Unlockable) {
+            if let Err(msg) = self.challenges.verify(
+                &self.manager.config.challenge_policy,
+                current_time,
+                ChallengeAction::UnlockTimer,
+                challenge,
+            ) {
+                return Response::Error { msg, rule: None };
+            }
             match self.manager.constraints.break_timer.unlock(current_time) {
                 Ok(()) => {
                     self.refresh_cache(current_time);
                     Response::Success
                 }
-                Err(msg) => Response::Error { msg },
+                Err(msg) => Response::Error { msg, rule: None },
             }
         } else {
             Response::Error {
                 msg: "Session is not unlockable.".to_owned(),
+                rule: None,
             }
         }
     }
     pub fn lock_timer(&mut self, current_time: Timestamp) -> Response {
-        self.manager.constraints.deactivated_until = None;
+        self.manager.constraints.end_deactivation(current_time);
         self.refresh_cache(current_time);
         match self.manager.constraints.break_timer.lock(current_time) {
             Ok(()) => {
                 self.refresh_cache(current_time);
                 Response::Success
             }
-            Err(msg) => Response::Error { msg },
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn report_idle(&mut self, current_time: Timestamp, since: Timestamp) -> Response {
+        self.refresh_cache(current_time);
+        self.manager
+            .constraints
+            .break_timer
+            .report_idle(current_time, since);
+        self.refresh_cache(current_time);
+        Response::Success
+    }
+    pub fn report_activity(&mut self, current_time: Timestamp) -> Response {
+        self.refresh_cache(current_time);
+        self.manager
+            .constraints
+            .break_timer
+            .report_activity(current_time);
+        self.refresh_cache(current_time);
+        Response::Success
+    }
+    pub fn pause_timer(&mut self, current_time: Timestamp) -> Response {
+        self.refresh_cache(current_time);
+        let Some(policy) = &self.manager.config.pause_policy else {
+            return Response::Error {
+                msg: "Pausing the break timer is not allowed.".to_owned(),
+                rule: None,
+            };
+        };
+        let break_timer = &mut self.manager.constraints.break_timer;
+        let max_duration = match policy.check_pause(break_timer.pause_used(current_time)) {
+            Ok(max_duration) => max_duration,
+            Err(violation) => {
+                return Response::Error {
+                    msg: violation.msg,
+                    rule: Some(violation.rule),
+                }
+            }
+        };
+        match break_timer.pause(current_time, max_duration.map(|max| current_time + max)) {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn resume_timer(&mut self, current_time: Timestamp) -> Response {
+        self.refresh_cache(current_time);
+        match self.manager.constraints.break_timer.resume(current_time) {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn extend_work_period(&mut self, current_time: Timestamp, duration: Duration) -> Response {
+        self.refresh_cache(current_time);
+        if duration <= Duration::ZERO {
+            return Response::Error {
+                msg: "Extension duration must be positive.".to_owned(),
+                rule: None,
+            };
+        }
+        let Some(policy) = &self.manager.config.extension_policy else {
+            return Response::Error {
+                msg: "Extending the work period is not allowed.".to_owned(),
+                rule: None,
+            };
+        };
+        let break_timer = &mut self.manager.constraints.break_timer;
+        if let Err(violation) = policy.check_extension(duration, break_timer.extended_today) {
+            return Response::Error {
+                msg: violation.msg,
+                rule: Some(violation.rule),
+            };
+        }
+        match break_timer.extend(current_time, duration) {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
         }
     }
     pub fn get_info(&self) -> CurrentInfo {
@@ -264,7 +960,17 @@ impl DiagonatorManager {
     }
     pub fn get_info_once(&mut self, current_time: Timestamp) -> Response {
         Response::Info {
-            info: self.refresh_cache(current_time),
+            info: Box::new(self.refresh_cache(current_time)),
+        }
+    }
+    pub fn get_timeline(&mut self, current_time: Timestamp) -> Response {
+        self.refresh_cache(current_time);
+        Response::Timeline {
+            segments: self
+                .manager
+                .constraints
+                .build_simulator(current_time)
+                .timeline(current_time),
         }
     }
     pub fn complete_requirement(
@@ -276,13 +982,15 @@ impl DiagonatorManager {
         match self
             .manager
             .constraints
-            .complete_requirement(requirement_id)
+            .complete_requirement(current_time, requirement_id)
         {
             Ok(()) => {
+                self.manager
+                    .schedule_next_instance(requirement_id, current_time);
                 self.refresh_cache(current_time);
                 Response::Success
             }
-            Err(msg) => Response::Error { msg },
+            Err(msg) => Response::Error { msg, rule: None },
         }
     }
     pub fn add_requirement(
@@ -290,31 +998,248 @@ impl DiagonatorManager {
         current_time: Timestamp,
         name: String,
         due: HourMinute,
+        available_from: Option<HourMinute>,
     ) -> Response {
         self.refresh_cache(current_time);
+        if let Some(available_from) = available_from.filter(|available_from| *available_from > due)
+        {
+            return Response::Error {
+                msg: format!(
+                    "Requirement cannot be due at {} if it can't be completed until {}.",
+                    due, available_from
+                ),
+                rule: None,
+            };
+        }
         self.manager.constraints.requirements.push(Requirement {
             id: self.manager.id_generator.next_id(),
             name,
             due: Timestamp::from_date_hm(&self.manager.current_date, &due),
             complete: false,
+            from_config: false,
+            max_postpones: None,
+            max_postpone_duration: None,
+            postponements: Vec::new(),
+            interval: None,
+            available_from: Timestamp::from_date_hm_opt(
+                &self.manager.current_date,
+                &available_from,
+            ),
+            completable: false,
         });
         self.refresh_cache(current_time);
         Response::Success
     }
-    pub fn deactivate(&mut self, current_time: Timestamp, duration: Duration) -> Response {
-        self.manager.constraints.deactivated_until = Some(current_time + duration);
+    pub fn postpone_requirement(
+        &mut self,
+        current_time: Timestamp,
+        requirement_id: u64,
+        duration: Duration,
+    ) -> Response {
+        self.refresh_cache(current_time);
+        match self
+            .manager
+            .constraints
+            .postpone_requirement(current_time, requirement_id, duration)
+        {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn remove_requirement(&mut self, current_time: Timestamp, requirement_id: u64) -> Response {
+        self.refresh_cache(current_time);
+        let allow_configured = self.manager.config.allow_removing_configured_requirements;
+        match self
+            .manager
+            .constraints
+            .remove_requirement(requirement_id, allow_configured)
+        {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn update_requirement(
+        &mut self,
+        current_time: Timestamp,
+        requirement_id: u64,
+        name: Option<String>,
+        due: Option<HourMinute>,
+    ) -> Response {
+        self.refresh_cache(current_time);
+        let allow_configured = self.manager.config.allow_removing_configured_requirements;
+        let due = due.map(|due| Timestamp::from_date_hm(&self.manager.current_date, &due));
+        match self.manager.constraints.update_requirement(
+            requirement_id,
+            name,
+            due,
+            allow_configured,
+        ) {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn add_locked_time_range(
+        &mut self,
+        current_time: Timestamp,
+        start: Option<HourMinute>,
+        end: Option<HourMinute>,
+    ) -> Response {
+        self.refresh_cache(current_time);
+        let ltr = LockedTimeRangeConfig {
+            start,
+            end,
+            days: None,
+        };
+        let id = self.manager.id_generator.next_id();
+        self.manager
+            .constraints
+            .locked_time_ranges
+            .push(TimeRange::starting_on(
+                id,
+                self.manager.current_date,
+                &ltr,
+                false,
+            ));
         self.refresh_cache(current_time);
         Response::Success
     }
+    pub fn remove_locked_time_range(&mut self, current_time: Timestamp, range_id: u64) -> Response {
+        self.refresh_cache(current_time);
+        match self
+            .manager
+            .constraints
+            .remove_locked_time_range(current_time, range_id)
+        {
+            Ok(()) => {
+                self.refresh_cache(current_time);
+                Response::Success
+            }
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn get_challenge(&mut self, current_time: Timestamp, action: ChallengeAction) -> Response {
+        match self
+            .challenges
+            .issue(&self.manager.config.challenge_policy, current_time, action)
+        {
+            Ok(challenge) => Response::Challenge { challenge },
+            Err(msg) => Response::Error { msg, rule: None },
+        }
+    }
+    pub fn deactivate(
+        &mut self,
+        current_time: Timestamp,
+        duration: Duration,
+        challenge: Option<&ChallengeAnswer>,
+    ) -> Response {
+        self.refresh_cache(current_time);
+        if duration <= Duration::ZERO {
+            return Response::Error {
+                msg: "Deactivation duration must be positive.".to_owned(),
+                rule: None,
+            };
+        }
+        let constraints = &mut self.manager.constraints;
+        // the policy also applies to reasons that would start during the deactivation, since
+        // they wouldn't be enforced until it ends
+        let reasons = constraints.reasons_until(current_time, current_time + duration);
+        if let Err(violation) = self.manager.config.deactivation_policy.check(
+            current_time,
+            duration,
+            &reasons,
+            &constraints.deactivations,
+        ) {
+            return Response::Error {
+                msg: violation.msg,
+                rule: Some(violation.rule),
+            };
+        }
+        if let Err(msg) = self.challenges.verify(
+            &self.manager.config.challenge_policy,
+            current_time,
+            ChallengeAction::Deactivate,
+            challenge,
+        ) {
+            return Response::Error { msg, rule: None };
+        }
+        let constraints = &mut self.manager.constraints;
+        constraints.deactivated_until = Some(current_time + duration);
+        constraints.deactivations.push(Deactivation {
+            start: current_time,
+            duration,
+        });
+        self.refresh_cache(current_time);
+        Response::Success
+    }
+    pub fn reload_config(&mut self, current_time: Timestamp, config: DiagonatorManagerConfig) {
+        self.refresh_cache(current_time);
+        self.manager.reload_config(config);
+        self.refresh_cache(current_time);
+    }
+    /// Logs a request that was handled at `current_time`. `info` should be the result of
+    /// `get_info_at` from before the request was handled.
+    pub fn log_request<R: Serialize>(
+        &self,
+        current_time: Timestamp,
+        request: &R,
+        response: &Response,
+        info: &CurrentInfo,
+    ) {
+        self.event_log
+            .log_request(current_time, request, response, info);
+    }
+    pub fn get_info_at(&mut self, current_time: Timestamp) -> CurrentInfo {
+        self.refresh_cache(current_time)
+    }
+    /// Returns the lock warnings that were issued since the last call.
+    pub fn take_lock_warnings(&mut self) -> Vec<LockWarning> {
+        std::mem::take(&mut self.pending_lock_warnings)
+    }
+    pub fn set_overlay_status(&mut self, current_time: Timestamp, overlay: Option<OverlayStatus>) {
+        self.overlay = overlay;
+        self.refresh_cache(current_time);
+    }
     fn refresh_cache(&mut self, current_time: Timestamp) -> CurrentInfo {
         self.cache_time = current_time;
-        let new_info = self.manager.refresh(current_time);
+        let mut new_info = self.manager.refresh(current_time);
+        new_info.overlay = self.overlay.clone();
         if new_info != self.cached_info {
+            if new_info.is_transition_from(&self.cached_info) {
+                self.event_log.log_transition(current_time, &new_info);
+                run_hooks(
+                    &self.manager.config.hooks,
+                    current_time,
+                    &self.cached_info,
+                    &new_info,
+                );
+            }
             self.cached_info = new_info.clone();
             self.cache_version += 1;
         }
+        let policy = &self.manager.config.lock_warning_policy;
+        if let Some(warning) = self.lock_warnings.check(policy, current_time, &new_info) {
+            run_lock_warning_hook(policy, &warning);
+            self.pending_lock_warnings.push(warning);
+        }
+        self.save_state();
         new_info
     }
+    fn save_state(&mut self) {
+        let snapshot = self.manager.snapshot();
+        if self.saved_snapshot.as_ref() != Some(&snapshot) {
+            self.state_file.save(&snapshot);
+            self.saved_snapshot = Some(snapshot);
+        }
+    }
 }
 
 struct DiagonatorManagerInner {
@@ -326,48 +1251,271 @@ struct DiagonatorManagerInner {
 
 impl DiagonatorManagerInner {
     pub fn new(config: DiagonatorManagerConfig) -> Self {
-        let break_timer =
-            BreakTimerManager::new(config.work_period_duration, config.break_duration);
+        let break_timer = BreakTimerManager::new(&config);
         Self {
             config,
             constraints: Constraints {
                 break_timer,
                 requirements: Vec::new(),
                 locked_time_ranges: Vec::new(),
+                upcoming_requirements: Vec::new(),
+                upcoming_locked_time_ranges: Vec::new(),
                 deactivated_until: None,
+                deactivations: Vec::new(),
             },
             current_date: Timestamp::ZERO.get_date(),
             id_generator: IdGenerator::new(),
         }
     }
-    fn new_day(&mut self) {
-        self.constraints.requirements = self
-            .config
+    fn requirements_for(&mut self, date: LocalDate) -> Vec<Requirement> {
+        let weekday = date.weekday();
+        self.config
             .requirements
             .iter()
-            .map(|req| Requirement {
-                id: self.id_generator.next_id(),
-                name: req.name.clone(),
-                due: Timestamp::from_date_hm(&self.current_date, &req.due),
-                complete: false,
+            .filter(|req| req.is_active_on(weekday))
+            .map(|req| {
+                let due = Timestamp::from_date_hm(&date, &req.due);
+                Requirement {
+                    id: self.id_generator.next_id(),
+                    name: req.name.clone(),
+                    due,
+                    complete: false,
+                    from_config: true,
+                    // configured requirements can only be postponed if the configuration allows it
+                    max_postpones: Some(req.max_postpones.unwrap_or(0)),
+                    max_postpone_duration: req.max_postpone_minutes.map(Duration::from_minutes),
+                    postponements: Vec::new(),
+                    // only the first instance of a recurring requirement is created here, and
+                    // the others are created as the previous ones are completed
+                    interval: req
+                        .interval_minutes
+                        .filter(|minutes| *minutes > 0)
+                        .map(|minutes| RequirementInterval {
+                            every: Duration::from_minutes(minutes),
+                            first_due: due,
+                            last_due: match &req.last_due {
+                                Some(last_due) => Timestamp::from_date_hm(&date, last_due),
+                                None => Timestamp::start_of_day(&date.succ()),
+                            },
+                            fixed_schedule: req.fixed_schedule.unwrap_or(false),
+                        }),
+                    available_from: Timestamp::from_date_hm_opt(&date, &req.available_from),
+                    completable: false,
+                }
             })
-            .collect();
-        self.constraints.locked_time_ranges = self
-            .config
-            .locked_time_ranges
+            .collect()
+    }
+    /// Creates the configured locked time ranges that start on `date`.
+    fn locked_time_ranges_for(&mut self, date: LocalDate) -> Vec<TimeRange> {
+        let mut locked_time_ranges = Vec::new();
+        for ltr in &self.config.locked_time_ranges {
+            if ltr.is_active_on(date.weekday()) {
+                locked_time_ranges.push(TimeRange::starting_on(
+                    self.id_generator.next_id(),
+                    date,
+                    ltr,
+                    true,
+                ));
+            }
+        }
+        locked_time_ranges
+    }
+    /// Creates all of the configured locked time ranges that cover part of `date`, for when the
+    /// previous day's ranges aren't available: a range that crosses midnight and started the day
+    /// before still covers the start of `date`.
+    fn all_locked_time_ranges_for(&mut self, date: LocalDate) -> Vec<TimeRange> {
+        let yesterday = date.pred();
+        let mut locked_time_ranges = Vec::new();
+        for ltr in &self.config.locked_time_ranges {
+            if ltr.crosses_midnight() && ltr.is_active_on(yesterday.weekday()) {
+                locked_time_ranges.push(TimeRange::starting_on(
+                    self.id_generator.next_id(),
+                    yesterday,
+                    ltr,
+                    true,
+                ));
+            }
+        }
+        locked_time_ranges.extend(self.locked_time_ranges_for(date));
+        locked_time_ranges
+    }
+    fn new_day(&mut self, previous_date: LocalDate) {
+        self.constraints.deactivations.clear();
+        self.constraints.break_timer.completed_cycles = 0;
+        self.constraints.break_timer.paused_today = Duration::ZERO;
+        self.constraints.break_timer.extended_today = Duration::ZERO;
+        if previous_date.succ() == self.current_date {
+            // the previous day already generated today's requirements and locked time ranges,
+            // so we reuse them to keep their IDs stable, along with the previous day's ranges
+            // that cross midnight, including ones that were added at runtime
+            let day_start = Timestamp::start_of_day(&self.current_date);
+            let carried_over = std::mem::take(&mut self.constraints.locked_time_ranges)
+                .into_iter()
+                .filter(|ltr| ltr.end.is_some_and(|end| end > day_start));
+            self.constraints.requirements =
+                std::mem::take(&mut self.constraints.upcoming_requirements);
+            self.constraints.locked_time_ranges = carried_over
+                .chain(std::mem::take(
+                    &mut self.constraints.upcoming_locked_time_ranges,
+                ))
+                .collect();
+        } else {
+            self.constraints.requirements = self.requirements_for(self.current_date);
+            self.constraints.locked_time_ranges =
+                self.all_locked_time_ranges_for(self.current_date);
+        }
+        self.plan_next_day();
+    }
+    /// Creates the next day's requirements and locked time ranges. Ranges that start today and
+    /// cross midnight are already part of today's ranges, so they aren't created again.
+    fn plan_next_day(&mut self) {
+        let tomorrow = self.current_date.succ();
+        self.constraints.upcoming_requirements = self.requirements_for(tomorrow);
+        self.constraints.upcoming_locked_time_ranges = self.locked_time_ranges_for(tomorrow);
+    }
+    /// Replaces the configuration and applies it to the current day.
+    fn reload_config(&mut self, config: DiagonatorManagerConfig) {
+        self.constraints.break_timer.work_period_duration = config.work_period_duration;
+        self.constraints.break_timer.break_duration = config.break_duration;
+        self.constraints.break_timer.long_break_duration = config.long_break_duration;
+        self.constraints.break_timer.long_break_interval = config.long_break_interval;
+        self.config = config;
+        self.apply_config();
+    }
+    /// Rebuilds the current day's requirements and locked time ranges from the configuration.
+    /// Requirements and locked time ranges that were added at runtime are kept, and configured
+    /// requirements whose names haven't changed keep their ID, completion status and
+    /// postponements. Each old requirement is matched at most once, preferring one with the same
+    /// deadline, so that requirements that share a name stay separate.
+    fn apply_config(&mut self) {
+        let mut old_requirements = std::mem::take(&mut self.constraints.requirements);
+        let old_locked_time_ranges = std::mem::take(&mut self.constraints.locked_time_ranges);
+        self.constraints.requirements = self.requirements_for(self.current_date);
+        self.constraints.locked_time_ranges = self.all_locked_time_ranges_for(self.current_date);
+        self.plan_next_day();
+        let mut matched = vec![false; self.constraints.requirements.len()];
+        for same_due in [true, false] {
+            for (req, matched) in self.constraints.requirements.iter_mut().zip(&mut matched) {
+                if *matched {
+                    continue;
+                }
+                let Some(index) = old_requirements.iter().position(|old_req| {
+                    old_req.from_config
+                        && old_req.name == req.name
+                        && (!same_due || old_req.due == req.due + old_req.total_postponement())
+                }) else {
+                    continue;
+                };
+                let old_req = old_requirements.remove(index);
+                req.id = old_req.id;
+                req.complete = old_req.complete;
+                req.due = req.due + old_req.total_postponement();
+                req.postponements = old_req.postponements;
+                *matched = true;
+            }
+        }
+        // later instances of recurring requirements are kept, with the new interval settings
+        let later_instances: Vec<Requirement> = old_requirements
             .iter()
-            .map(|ltr| TimeRange {
-                id: self.id_generator.next_id(),
-                start: Timestamp::from_date_hm_opt(&self.current_date, &ltr.start),
-                end: Timestamp::from_date_hm_opt(&self.current_date, &ltr.end),
+            .filter(|old_req| old_req.from_config)
+            .filter_map(|old_req| {
+                let interval = self
+                    .constraints
+                    .requirements
+                    .iter()
+                    .find(|req| req.name == old_req.name)?
+                    .interval
+                    .clone()?;
+                Some(Requirement {
+                    interval: Some(interval),
+                    ..old_req.clone()
+                })
             })
             .collect();
+        self.constraints.requirements.extend(later_instances);
+        self.constraints.requirements.extend(
+            old_requirements
+                .into_iter()
+                .filter(|old_req| !old_req.from_config),
+        );
+        self.constraints.locked_time_ranges.extend(
+            old_locked_time_ranges
+                .into_iter()
+                .filter(|old_ltr| !old_ltr.from_config),
+        );
+    }
+    /// Creates the next instance of a recurring requirement after the instance `id` was
+    /// completed, unless it would be due after the requirement's last due time.
+    fn schedule_next_instance(&mut self, id: u64, completion_time: Timestamp) {
+        let Some(req) = self
+            .constraints
+            .requirements
+            .iter()
+            .find(|req| req.id == id)
+        else {
+            return;
+        };
+        let Some(interval) = &req.interval else {
+            return;
+        };
+        let due = interval.next_due(completion_time);
+        if due > interval.last_due {
+            return;
+        }
+        // each instance can be completed as long before it is due as the first one
+        let available_from = req
+            .available_from
+            .map(|available_from| due + (available_from - req.due) + req.total_postponement());
+        let next = Requirement {
+            id: self.id_generator.next_id(),
+            due,
+            complete: false,
+            postponements: Vec::new(),
+            available_from,
+            ..req.clone()
+        };
+        self.constraints.requirements.push(next);
+    }
+    fn snapshot(&self) -> ManagerSnapshot {
+        ManagerSnapshot {
+            date: self.current_date.naive_local(),
+            timer: self.constraints.break_timer.timer.clone(),
+            completed_cycles: self.constraints.break_timer.completed_cycles,
+            paused_today: self.constraints.break_timer.paused_today,
+            extended_today: self.constraints.break_timer.extended_today,
+            requirements: self.constraints.requirements.clone(),
+            locked_time_ranges: self.constraints.locked_time_ranges.clone(),
+            upcoming_requirements: self.constraints.upcoming_requirements.clone(),
+            upcoming_locked_time_ranges: self.constraints.upcoming_locked_time_ranges.clone(),
+            deactivated_until: self.constraints.deactivated_until,
+            deactivations: self.constraints.deactivations.clone(),
+            last_id: self.id_generator.last_id,
+        }
+    }
+    fn restore(&mut self, snapshot: ManagerSnapshot) {
+        // the caller has already checked that the snapshot belongs to the current date
+        if let Some(date) = chrono::Local.from_local_date(&snapshot.date).single() {
+            self.current_date = date;
+        }
+        self.constraints.break_timer.timer = snapshot.timer;
+        self.constraints.break_timer.completed_cycles = snapshot.completed_cycles;
+        self.constraints.break_timer.paused_today = snapshot.paused_today;
+        self.constraints.break_timer.extended_today = snapshot.extended_today;
+        self.constraints.requirements = snapshot.requirements;
+        self.constraints.locked_time_ranges = snapshot.locked_time_ranges;
+        self.constraints.upcoming_requirements = snapshot.upcoming_requirements;
+        self.constraints.upcoming_locked_time_ranges = snapshot.upcoming_locked_time_ranges;
+        self.constraints.deactivated_until = snapshot.deactivated_until;
+        self.constraints.deactivations = snapshot.deactivations;
+        self.id_generator.last_id = snapshot.last_id;
+        // the configuration file may have changed while the server wasn't running
+        self.apply_config();
     }
     fn refresh(&mut self, current_time: Timestamp) -> CurrentInfo {
         let current_date = current_time.get_date();
         if current_date != self.current_date {
-            self.current_date = current_date;
-            self.new_day();
+            let previous_date = std::mem::replace(&mut self.current_date, current_date);
+            self.new_day(previous_date);
         }
         let mut current_info = self.constraints.get_current_info(current_time);
 
@@ -377,6 +1525,18 @@ impl DiagonatorManagerInner {
                 current_info = self.constraints.get_current_info(current_time);
             }
         }
+        let break_timer = &self.constraints.break_timer;
+        current_info.pause_quota = self
+            .config
+            .pause_policy
+            .as_ref()
+            // the current pause isn't included, so that the info doesn't change every second
+            .map(|policy| policy.quota(break_timer.paused_today));
+        current_info.extension_quota = self
+            .config
+            .extension_policy
+            .as_ref()
+            .map(|policy| policy.quota(break_timer.extended_today));
         current_info
     }
 }
@@ -386,6 +1546,19 @@ pub struct DiagonatorManagerConfig {
     pub locked_time_ranges: Vec<LockedTimeRangeConfig>,
     pub work_period_duration: Duration,
     pub break_duration: Duration,
+    pub long_break_duration: Option<Duration>,
+    pub long_break_interval: u32,
+    /// `None` if the work period can't be paused
+    pub pause_policy: Option<TimerQuotaPolicy>,
+    /// `None` if the work period can't be extended
+    pub extension_policy: Option<TimerQuotaPolicy>,
+    /// Removing a configured requirement or moving its deadline back would be an easy way to
+    /// avoid it, so this is only allowed if the configuration explicitly says so.
+    pub allow_removing_configured_requirements: bool,
+    pub deactivation_policy: DeactivationPolicy,
+    pub challenge_policy: ChallengePolicy,
+    pub hooks: Vec<HookConfig>,
+    pub lock_warning_policy: LockWarningPolicy,
 }
 
 struct IdGenerator {
@@ -401,3 +1574,186 @@ impl IdGenerator {
         Self { last_id: 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> Timestamp {
+        Timestamp::ZERO + Duration::from_minutes(minutes)
+    }
+
+    /// A break timer with 25 minute work periods, 5 minute breaks and, if `long_break_minutes`
+    /// is given, a long break after every 3 work periods.
+    fn break_timer(long_break_minutes: Option<i64>) -> BreakTimerManager {
+        BreakTimerManager {
+            timer: BreakTimer::Unlockable,
+            work_period_duration: Duration::from_minutes(25),
+            break_duration: Duration::from_minutes(5),
+            long_break_duration: long_break_minutes.map(Duration::from_minutes),
+            long_break_interval: 3,
+            completed_cycles: 0,
+            paused_today: Duration::ZERO,
+            extended_today: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn every_third_break_is_long() {
+        let mut timer = break_timer(Some(15));
+        let mut t = 0;
+        for (cycle, break_minutes) in [5, 5, 15, 5, 5, 15].into_iter().enumerate() {
+            timer.unlock(at(t)).unwrap();
+            assert_eq!(timer.cycles_until_long_break(), Some(3 - cycle as u32 % 3));
+            t += 25;
+            timer.refresh(at(t));
+            assert_eq!(
+                timer.timer,
+                BreakTimer::Locked {
+                    until: at(t + break_minutes)
+                }
+            );
+            assert_eq!(timer.completed_cycles, cycle as u32 + 1);
+            t += break_minutes;
+            timer.refresh(at(t));
+            assert_eq!(timer.timer, BreakTimer::Unlockable);
+        }
+    }
+
+    #[test]
+    fn locking_early_completes_a_cycle() {
+        let mut timer = break_timer(Some(15));
+        timer.unlock(at(0)).unwrap();
+        timer.lock(at(10)).unwrap();
+        assert_eq!(timer.timer, BreakTimer::Locked { until: at(15) });
+        assert_eq!(timer.completed_cycles, 1);
+        assert_eq!(timer.cycles_until_long_break(), Some(2));
+    }
+
+    #[test]
+    fn reporting_idle_does_not_extend_the_work_period() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.report_idle(at(24), Timestamp::ZERO);
+        assert_eq!(
+            timer.timer,
+            BreakTimer::Idle {
+                since: at(0),
+                reported_at: at(24),
+                remaining: Duration::from_minutes(1),
+            }
+        );
+        timer.report_activity(at(24));
+        assert_eq!(timer.timer, BreakTimer::Unlocked { until: at(25) });
+    }
+
+    #[test]
+    fn short_idle_time_pauses_the_work_period() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.report_idle(at(10), at(8));
+        timer.report_activity(at(14));
+        assert_eq!(timer.timer, BreakTimer::Unlocked { until: at(29) });
+        assert_eq!(timer.completed_cycles, 0);
+    }
+
+    #[test]
+    fn idle_time_turns_into_a_break() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.report_idle(at(10), at(8));
+        timer.refresh(at(14));
+        assert!(matches!(timer.timer, BreakTimer::Idle { .. }));
+        timer.refresh(at(15));
+        assert_eq!(timer.timer, BreakTimer::Unlockable);
+        assert_eq!(timer.completed_cycles, 1);
+        // coming back after the break doesn't unlock the timer by itself
+        timer.report_activity(at(20));
+        assert_eq!(timer.timer, BreakTimer::Unlockable);
+    }
+
+    #[test]
+    fn idle_time_before_a_long_break_keeps_the_timer_locked() {
+        let mut timer = break_timer(Some(15));
+        timer.completed_cycles = 2;
+        timer.unlock(at(0)).unwrap();
+        timer.report_idle(at(10), at(10));
+        timer.refresh(at(15));
+        assert_eq!(timer.timer, BreakTimer::Locked { until: at(25) });
+        assert_eq!(timer.completed_cycles, 3);
+    }
+
+    #[test]
+    fn pause_resumes_automatically() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.pause(at(10), Some(at(20))).unwrap();
+        timer.refresh(at(19));
+        assert!(matches!(timer.timer, BreakTimer::Paused { .. }));
+        timer.refresh(at(20));
+        assert_eq!(timer.timer, BreakTimer::Unlocked { until: at(35) });
+        assert_eq!(timer.paused_today, Duration::from_minutes(10));
+    }
+
+    #[test]
+    fn pause_resumes_and_ends_in_one_refresh() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.pause(at(10), Some(at(20))).unwrap();
+        // the resumed work period ends at 35 and the break at 40
+        timer.refresh(at(36));
+        assert_eq!(timer.timer, BreakTimer::Locked { until: at(40) });
+        assert_eq!(timer.paused_today, Duration::from_minutes(10));
+        assert_eq!(timer.completed_cycles, 1);
+    }
+
+    #[test]
+    fn time_spent_paused_is_counted() {
+        let mut timer = break_timer(None);
+        timer.unlock(at(0)).unwrap();
+        timer.pause(at(5), None).unwrap();
+        assert_eq!(timer.pause_used(at(8)), Duration::from_minutes(3));
+        // the current pause isn't included until it ends
+        assert_eq!(timer.paused_today, Duration::ZERO);
+        timer.resume(at(8)).unwrap();
+        assert_eq!(timer.timer, BreakTimer::Unlocked { until: at(28) });
+        assert_eq!(timer.paused_today, Duration::from_minutes(3));
+        // locking during a pause also ends it
+        timer.pause(at(10), None).unwrap();
+        timer.lock(at(14)).unwrap();
+        assert_eq!(timer.paused_today, Duration::from_minutes(7));
+        assert_eq!(timer.pause_used(at(20)), Duration::from_minutes(7));
+        assert!(timer.resume(at(20)).is_err());
+    }
+
+    #[test]
+    fn time_extended_is_counted() {
+        let mut timer = break_timer(None);
+        assert!(timer.extend(at(0), Duration::from_minutes(5)).is_err());
+        timer.unlock(at(0)).unwrap();
+        timer.extend(at(5), Duration::from_minutes(5)).unwrap();
+        timer.extend(at(6), Duration::from_minutes(2)).unwrap();
+        assert_eq!(timer.timer, BreakTimer::Unlocked { until: at(32) });
+        assert_eq!(timer.extended_today, Duration::from_minutes(7));
+        timer.pause(at(10), None).unwrap();
+        assert!(timer.extend(at(11), Duration::from_minutes(5)).is_err());
+        assert_eq!(timer.extended_today, Duration::from_minutes(7));
+    }
+
+    #[test]
+    fn breaks_are_short_without_long_breaks() {
+        let mut timer = break_timer(None);
+        for cycle in 0..4 {
+            timer.unlock(at(cycle * 30)).unwrap();
+            timer.refresh(at(cycle * 30 + 25));
+            assert_eq!(
+                timer.timer,
+                BreakTimer::Locked {
+                    until: at(cycle * 30 + 30)
+                }
+            );
+            timer.refresh(at(cycle * 30 + 30));
+        }
+        assert_eq!(timer.cycles_until_long_break(), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::manager::ReasonKind;
+use crate::time::{Duration, Timestamp};
+use serde::{Deserialize, Serialize};
+
+/// Identifies the configured rule that caused a request to be rejected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum PolicyRule {
+    MaxDeactivationDuration {
+        max: Duration,
+    },
+    DeactivationBudget {
+        budget: Duration,
+        remaining: Duration,
+    },
+    DeactivationCooldown {
+        until: Timestamp,
+    },
+    DeactivationDeniedReason {
+        reason: ReasonKind,
+    },
+    PauseBudget {
+        budget: Duration,
+    },
+    MaxExtensionDuration {
+        max: Duration,
+    },
+    ExtensionBudget {
+        budget: Duration,
+        remaining: Duration,
+    },
+}
+
+#[derive(Debug)]
+pub struct PolicyViolation {
+    pub rule: PolicyRule,
+    pub msg: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Deactivation {
+    pub start: Timestamp,
+    pub duration: Duration,
+}
+
+impl Deactivation {
+    fn end(&self) -> Timestamp {
+        self.start + self.duration
+    }
+}
+
+/// Limits on `Deactivate` requests. `None` means that there is no limit.
+#[derive(Default)]
+pub struct DeactivationPolicy {
+    pub max_duration: Option<Duration>,
+    pub daily_budget: Option<Duration>,
+    pub cooldown: Option<Duration>,
+    pub denied_reasons: Vec<ReasonKind>,
+}
+
+impl DeactivationPolicy {
+    /// Checks whether a deactivation is allowed, given the reasons for the states that the
+    /// deactivation would cover and the deactivations that have already happened today.
+    pub fn check(
+        &self,
+        current_time: Timestamp,
+        duration: Duration,
+        reasons: &[ReasonKind],
+        history: &[Deactivation],
+    ) -> Result<(), PolicyViolation> {
+        if let Some(&reason) = reasons
+            .iter()
+            .find(|reason| self.denied_reasons.contains(reason))
+        {
+            return Err(PolicyViolation {
+                rule: PolicyRule::DeactivationDeniedReason { reason },
+                msg: format!("Deactivation is not allowed during {:?}.", reason),
+            });
+        }
+        if let Some(max) = self.max_duration {
+            if duration > max {
+                return Err(PolicyViolation {
+                    rule: PolicyRule::MaxDeactivationDuration { max },
+                    msg: format!("Deactivation cannot last longer than {}.", max),
+                });
+            }
+        }
+        if let Some(budget) = self.daily_budget {
+            let used: Duration = history.iter().map(|d| d.duration).sum();
+            let remaining = if used < budget {
+                budget - used
+            } else {
+                Duration::ZERO
+            };
+            if duration > remaining {
+                return Err(PolicyViolation {
+                    rule: PolicyRule::DeactivationBudget { budget, remaining },
+                    msg: format!(
+                        "Deactivation exceeds the daily budget of {} ({} remaining).",
+                        budget, remaining
+                    ),
+                });
+            }
+        }
+        if let (Some(cooldown), Some(last)) = (self.cooldown, history.last()) {
+            let until = last.end() + cooldown;
+            if current_time < until {
+                return Err(PolicyViolation {
+                    rule: PolicyRule::DeactivationCooldown { until },
+                    msg: format!("Deactivation is on cooldown until {}.", until.format_hm()),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Limits on pausing or extending the work period. `None` means that there is no limit.
+#[derive(Debug, Clone, Default)]
+pub struct TimerQuotaPolicy {
+    pub max_duration: Option<Duration>,
+    pub daily_budget: Option<Duration>,
+}
+
+/// How much of a quota has been used today, as reported in `CurrentInfo`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TimerQuota {
+    pub used: Duration,
+    /// `None` if there is no daily budget
+    pub remaining: Option<Duration>,
+    pub max_duration: Option<Duration>,
+}
+
+impl TimerQuotaPolicy {
+    fn remaining(&self, used: Duration) -> Option<Duration> {
+        self.daily_budget.map(|budget| {
+            if used < budget {
+                budget - used
+            } else {
+                Duration::ZERO
+            }
+        })
+    }
+    pub fn quota(&self, used: Duration) -> TimerQuota {
+        TimerQuota {
+            used,
+            remaining: self.remaining(used),
+            max_duration: self.max_duration,
+        }
+    }
+    /// Checks whether the work period can be paused, returning the longest that the pause can
+    /// last.
+    pub fn check_pause(&self, used: Duration) -> Result<Option<Duration>, PolicyViolation> {
+        let remaining = self.remaining(used);
+        if let (Some(budget), Some(Duration::ZERO)) = (self.daily_budget, remaining) {
+            return Err(PolicyViolation {
+                rule: PolicyRule::PauseBudget { budget },
+                msg: format!("The daily pause budget of {} has been used up.", budget),
+            });
+        }
+        Ok(match (self.max_duration, remaining) {
+            (Some(max), Some(remaining)) => Some(max.min(remaining)),
+            (max, remaining) => max.or(remaining),
+        })
+    }
+    /// Checks whether the work period can be extended by `duration`.
+    pub fn check_extension(
+        &self,
+        duration: Duration,
+        used: Duration,
+    ) -> Result<(), PolicyViolation> {
+        if let Some(max) = self.max_duration {
+            if duration > max {
+                return Err(PolicyViolation {
+                    rule: PolicyRule::MaxExtensionDuration { max },
+                    msg: format!("The work period cannot be extended by more than {}.", max),
+                });
+            }
+        }
+        if let (Some(budget), Some(remaining)) = (self.daily_budget, self.remaining(used)) {
+            if duration > remaining {
+                return Err(PolicyViolation {
+                    rule: PolicyRule::ExtensionBudget { budget, remaining },
+                    msg: format!(
+                        "Extension exceeds the daily budget of {} ({} remaining).",
+                        budget, remaining
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::line_protocol::Event;
+use crate::manager::CurrentInfo;
+use axum::extract::ws::{Message, WebSocket};
+use axum::response::sse::{self, KeepAlive, Sse};
+use std::convert::Infallible;
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// Streams `info_update` events, starting with the current info. Each event's ID is the version
+/// of the info, so that clients can tell whether they missed an update.
+pub fn info_event_stream(
+    info_updates: watch::Receiver<(CurrentInfo, u64)>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = WatchStream::new(info_updates).map(|(info, version)| {
+        Ok(sse::Event::default()
+            .event("info_update")
+            .id(version.to_string())
+            .json_data(info)
+            .unwrap())
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Sends an `InfoUpdate` message, like the ones from the line protocol, right away and whenever
+/// the info changes. Messages from the client are ignored.
+pub async fn push_info_updates(
+    mut socket: WebSocket,
+    mut info_updates: watch::Receiver<(CurrentInfo, u64)>,
+) {
+    loop {
+        let (info, version) = info_updates.borrow_and_update().clone();
+        let message = serde_json::to_string(&Event::InfoUpdate { version, info }).unwrap();
+        if socket.send(Message::Text(message)).await.is_err() {
+            return;
+        }
+        loop {
+            tokio::select! {
+                changed = info_updates.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    break;
+                }
+                message = socket.recv() => {
+                    if !matches!(message, Some(Ok(_))) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
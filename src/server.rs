@@ -1,90 +1,540 @@
-use crate::config::DiagonatorConfig;
+use crate::auth::{Access, Tokens};
+use crate::challenge::{ChallengeAction, ChallengeAnswer, ChallengePolicy, IssuedChallenge};
+use crate::config::{read_config, DiagonatorConfig, TimerQuotaConfig};
+use crate::events::EventLog;
+use crate::line_protocol::LineServer;
 use crate::manager::{CurrentInfo, DiagonatorManager, DiagonatorManagerConfig};
+use crate::policy::{DeactivationPolicy, PolicyRule, TimerQuotaPolicy};
+use crate::push::{info_event_stream, push_info_updates};
+use crate::simulator::TimelineSegment;
+use crate::state::StateFile;
+use crate::supervisor::{OverlayCommand, Supervisor};
 use crate::time::{Duration, HourMinute, Timestamp};
-use axum::routing::post;
+#[cfg(unix)]
+use crate::unix_socket::UnixConnection;
+use crate::warnings::LockWarningPolicy;
+use axum::extract::{Query, WebSocketUpgrade};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Extensions, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
 use axum::Json;
 use serde::{Deserialize, Serialize};
-use socketioxide::{extract::SocketRef, SocketIo};
+use socketioxide::extract::{SocketRef, TryData};
+use socketioxide::handler::ConnectHandler;
+use socketioxide::SocketIo;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::sync::watch;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
-enum Request {
-    UnlockTimer,
+pub enum Request {
+    UnlockTimer {
+        #[serde(default)]
+        challenge: Option<ChallengeAnswer>,
+    },
     LockTimer,
+    ReportIdle {
+        since: Timestamp,
+    },
+    ReportActivity,
+    PauseTimer,
+    ResumeTimer,
+    ExtendWorkPeriod {
+        duration: Duration,
+    },
     GetInfo,
-    CompleteRequirement { id: u64 },
-    AddRequirement { name: String, due: HourMinute },
-    Deactivate { duration: Duration },
+    GetTimeline,
+    GetChallenge {
+        action: ChallengeAction,
+    },
+    CompleteRequirement {
+        id: u64,
+    },
+    AddRequirement {
+        name: String,
+        due: HourMinute,
+        #[serde(default)]
+        available_from: Option<HourMinute>,
+    },
+    PostponeRequirement {
+        id: u64,
+        duration: Duration,
+    },
+    RemoveRequirement {
+        id: u64,
+    },
+    UpdateRequirement {
+        id: u64,
+        name: Option<String>,
+        due: Option<HourMinute>,
+    },
+    AddLockedTimeRange {
+        start: Option<HourMinute>,
+        end: Option<HourMinute>,
+    },
+    RemoveLockedTimeRange {
+        id: u64,
+    },
+    Deactivate {
+        duration: Duration,
+        #[serde(default)]
+        challenge: Option<ChallengeAnswer>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum Response {
     Success,
-    Error { msg: String },
-    Info { info: CurrentInfo },
+    Error {
+        msg: String,
+        /// the configured rule that caused the error, if there is one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rule: Option<PolicyRule>,
+    },
+    Info {
+        info: Box<CurrentInfo>,
+    },
+    Timeline {
+        segments: Vec<TimelineSegment>,
+    },
+    Challenge {
+        challenge: IssuedChallenge,
+    },
 }
 
-pub async fn launch_server(config: DiagonatorConfig) {
-    let (layer, io) = SocketIo::new_layer();
+impl Request {
+    fn required_access(&self) -> Access {
+        match self {
+            Self::GetInfo | Self::GetTimeline => Access::ReadOnly,
+            _ => Access::Control,
+        }
+    }
+}
+
+/// Socket.IO clients send their token in the handshake's auth payload.
+#[derive(Deserialize)]
+struct SocketAuth {
+    token: String,
+}
+
+#[derive(Deserialize, Default)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// Returns the bearer token from an `Authorization` header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Returns whether a request was received over the Unix socket, whose file permissions already
+/// restrict who can connect.
+fn is_unix_connection(extensions: &Extensions) -> bool {
+    #[cfg(unix)]
+    {
+        extensions.get::<UnixConnection>().is_some()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = extensions;
+        false
+    }
+}
+
+/// Returns the access granted to an HTTP request, which can authenticate with an
+/// `Authorization` header or, for clients like `EventSource` that can't set headers, with a
+/// `token` query parameter.
+fn http_access(
+    extensions: &Extensions,
+    headers: &HeaderMap,
+    query: &TokenQuery,
+    tokens: &Tokens,
+) -> Option<Access> {
+    if is_unix_connection(extensions) {
+        return Some(Access::Control);
+    }
+    bearer_token(headers)
+        .or(query.token.as_deref())
+        .and_then(|token| tokens.access(token))
+}
+
+/// Checks whether a client with `access` is allowed to send `request`, returning the HTTP status
+/// and an error message if it isn't.
+pub fn authorize(access: Option<Access>, request: &Request) -> Result<(), (StatusCode, String)> {
+    if access.is_none() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Invalid or missing token.".to_owned(),
+        ));
+    }
+    if access < Some(request.required_access()) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "This request requires a control token.".to_owned(),
+        ));
+    }
+    Ok(())
+}
 
-    let manager_config = DiagonatorManagerConfig {
-        requirements: config.requirements.unwrap_or(Vec::new()),
-        locked_time_ranges: config.locked_time_ranges.unwrap_or(Vec::new()),
+/// Handles an authorized request and records it in the event log.
+pub fn process_request(manager: &Mutex<DiagonatorManager>, request: &Request) -> Response {
+    let mut manager = manager.lock().unwrap();
+    let current_time = Timestamp::now();
+    let info = manager.get_info_at(current_time);
+    let response = handle_request(&mut manager, current_time, request);
+    manager.log_request(current_time, request, &response, &info);
+    response
+}
+
+fn handle_request(
+    manager: &mut DiagonatorManager,
+    current_time: Timestamp,
+    request: &Request,
+) -> Response {
+    match request {
+        Request::UnlockTimer { challenge } => {
+            manager.unlock_timer(current_time, challenge.as_ref())
+        }
+        Request::LockTimer => manager.lock_timer(current_time),
+        Request::ReportIdle { since } => manager.report_idle(current_time, *since),
+        Request::ReportActivity => manager.report_activity(current_time),
+        Request::PauseTimer => manager.pause_timer(current_time),
+        Request::ResumeTimer => manager.resume_timer(current_time),
+        Request::ExtendWorkPeriod { duration } => {
+            manager.extend_work_period(current_time, *duration)
+        }
+        Request::GetInfo => manager.get_info_once(current_time),
+        Request::GetTimeline => manager.get_timeline(current_time),
+        Request::GetChallenge { action } => manager.get_challenge(current_time, *action),
+        Request::CompleteRequirement { id } => manager.complete_requirement(current_time, *id),
+        Request::AddRequirement {
+            name,
+            due,
+            available_from,
+        } => manager.add_requirement(current_time, name.clone(), *due, *available_from),
+        Request::PostponeRequirement { id, duration } => {
+            manager.postpone_requirement(current_time, *id, *duration)
+        }
+        Request::RemoveRequirement { id } => manager.remove_requirement(current_time, *id),
+        Request::UpdateRequirement { id, name, due } => {
+            manager.update_requirement(current_time, *id, name.clone(), *due)
+        }
+        Request::AddLockedTimeRange { start, end } => {
+            manager.add_locked_time_range(current_time, *start, *end)
+        }
+        Request::RemoveLockedTimeRange { id } => {
+            manager.remove_locked_time_range(current_time, *id)
+        }
+        Request::Deactivate {
+            duration,
+            challenge,
+        } => manager.deactivate(current_time, *duration, challenge.as_ref()),
+    }
+}
+
+fn make_manager_config(config: &DiagonatorConfig) -> DiagonatorManagerConfig {
+    DiagonatorManagerConfig {
+        requirements: config.requirements.clone().unwrap_or_default(),
+        locked_time_ranges: config.locked_time_ranges.clone().unwrap_or_default(),
         work_period_duration: Duration::from_minutes(config.work_period_minutes),
         break_duration: Duration::from_minutes(config.break_minutes),
+        long_break_duration: config.long_break_minutes.map(Duration::from_minutes),
+        long_break_interval: config.long_break_interval.unwrap_or(4).max(1),
+        pause_policy: config.pause.as_ref().map(make_timer_quota_policy),
+        extension_policy: config.extension.as_ref().map(make_timer_quota_policy),
+        allow_removing_configured_requirements: config
+            .allow_removing_configured_requirements
+            .unwrap_or(false),
+        deactivation_policy: config
+            .deactivation
+            .as_ref()
+            .map(|deactivation| DeactivationPolicy {
+                max_duration: deactivation.max_minutes.map(Duration::from_minutes),
+                daily_budget: deactivation
+                    .daily_budget_minutes
+                    .map(Duration::from_minutes),
+                cooldown: deactivation.cooldown_minutes.map(Duration::from_minutes),
+                denied_reasons: deactivation.denied_reasons.clone().unwrap_or_default(),
+            })
+            .unwrap_or_default(),
+        challenge_policy: {
+            let challenges = config.challenges.clone().unwrap_or_default();
+            ChallengePolicy {
+                unlock_timer: challenges.unlock_timer,
+                deactivate: challenges.deactivate,
+                expiry: Duration::from_seconds(challenges.expiry_seconds.unwrap_or(120)),
+            }
+        },
+        hooks: config.hooks.clone().unwrap_or_default(),
+        lock_warning_policy: config
+            .lock_warnings
+            .as_ref()
+            .map(|lock_warnings| LockWarningPolicy {
+                thresholds: lock_warnings
+                    .minutes
+                    .iter()
+                    .copied()
+                    .map(Duration::from_minutes)
+                    .collect(),
+                command: lock_warnings.command.clone(),
+                args: lock_warnings.args.clone().unwrap_or_default(),
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn make_timer_quota_policy(config: &TimerQuotaConfig) -> TimerQuotaPolicy {
+    TimerQuotaPolicy {
+        max_duration: config.max_minutes.map(Duration::from_minutes),
+        daily_budget: config.daily_budget_minutes.map(Duration::from_minutes),
+    }
+}
+
+fn make_overlay_command(config: &DiagonatorConfig) -> Option<OverlayCommand> {
+    config.diagonator_path.as_ref().map(|path| OverlayCommand {
+        path: path.clone(),
+        args: config.diagonator_args.clone().unwrap_or_default(),
+    })
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+pub async fn launch_server(
+    config: DiagonatorConfig,
+    config_file_path: PathBuf,
+    state_file: StateFile,
+    event_log: EventLog,
+    tokens: Tokens,
+) {
+    let (layer, io) = SocketIo::new_layer();
+    let tokens: &'static Tokens = Box::leak(Box::new(tokens));
+
+    let manager: &'static Mutex<DiagonatorManager> =
+        Box::leak(Box::new(Mutex::new(DiagonatorManager::new(
+            make_manager_config(&config),
+            Timestamp::now(),
+            state_file,
+            event_log,
+        ))));
+    // every valid token can receive info updates, so the handshake only needs to be checked once
+    let authenticate = move |s: SocketRef, TryData(auth): TryData<SocketAuth>| {
+        if is_unix_connection(&s.req_parts().extensions) {
+            return Ok(());
+        }
+        let token = match &auth {
+            Ok(auth) => Some(auth.token.as_str()),
+            Err(_) => bearer_token(&s.req_parts().headers),
+        };
+        match token.and_then(|token| tokens.access(token)) {
+            Some(_) => Ok(()),
+            None => Err("Invalid or missing token."),
+        }
     };
-    let manager = Box::leak(Box::new(Mutex::new(DiagonatorManager::new(
-        manager_config,
-        Timestamp::now(),
-    ))));
-    io.ns("/", |s: SocketRef| {
-        s.emit("info_update", manager.lock().unwrap().get_info())
-            .ok();
-    });
-
-    let app = axum::Router::new()
-        .route(
-            "/",
-            post(|Json(request): Json<Request>| async {
-                let mut manager = manager.lock().unwrap();
-                let response = match request {
-                    Request::UnlockTimer => manager.unlock_timer(Timestamp::now()),
-                    Request::LockTimer => manager.lock_timer(Timestamp::now()),
-                    Request::GetInfo => manager.get_info_once(Timestamp::now()),
-                    Request::CompleteRequirement { id } => {
-                        manager.complete_requirement(Timestamp::now(), id)
-                    }
-                    Request::AddRequirement { name, due } => {
-                        manager.add_requirement(Timestamp::now(), name, due)
-                    }
-                    Request::Deactivate { duration } => {
-                        manager.deactivate(Timestamp::now(), duration)
-                    }
-                };
-                Json(response)
-            }),
-        )
-        .layer(layer);
+    io.ns(
+        "/",
+        (|s: SocketRef| {
+            s.emit("info_update", manager.lock().unwrap().get_info())
+                .ok();
+        })
+        .with(authenticate),
+    );
 
-    eprintln!("Server is listening on {}", &config.bind_on);
-    let listener = tokio::net::TcpListener::bind(config.bind_on).await.unwrap();
+    let (info_sender, info_receiver) = watch::channel(
+        manager
+            .lock()
+            .unwrap()
+            .get_info_if_changed(DiagonatorManager::NO_CACHE, Timestamp::now())
+            .unwrap(),
+    );
+    let events_info_receiver = info_receiver.clone();
+    let ws_info_receiver = info_receiver.clone();
+    let app =
+        axum::Router::new()
+            .route(
+                "/",
+                post(
+                    move |extensions: Extensions,
+                          headers: HeaderMap,
+                          Json(request): Json<Request>| async move {
+                        let access =
+                            http_access(&extensions, &headers, &TokenQuery::default(), tokens);
+                        match authorize(access, &request) {
+                            Ok(()) => (StatusCode::OK, Json(process_request(manager, &request))),
+                            Err((status, msg)) => {
+                                (status, Json(Response::Error { msg, rule: None }))
+                            }
+                        }
+                    },
+                ),
+            )
+            .route(
+                "/events",
+                get(
+                    move |extensions: Extensions,
+                          headers: HeaderMap,
+                          Query(query): Query<TokenQuery>| async move {
+                        match http_access(&extensions, &headers, &query, tokens) {
+                            Some(_) => info_event_stream(events_info_receiver).into_response(),
+                            None => (StatusCode::UNAUTHORIZED, "Invalid or missing token.")
+                                .into_response(),
+                        }
+                    },
+                ),
+            )
+            .route(
+                "/ws",
+                get(
+                    move |extensions: Extensions,
+                          headers: HeaderMap,
+                          Query(query): Query<TokenQuery>,
+                          ws: WebSocketUpgrade| async move {
+                        match http_access(&extensions, &headers, &query, tokens) {
+                            Some(_) => ws
+                                .on_upgrade(|socket| push_info_updates(socket, ws_info_receiver))
+                                .into_response(),
+                            None => (StatusCode::UNAUTHORIZED, "Invalid or missing token.")
+                                .into_response(),
+                        }
+                    },
+                ),
+            )
+            .layer(layer);
 
-    let server = async { axum::serve(listener, app).await.unwrap() };
+    let (overlay_command_sender, overlay_command_receiver) =
+        watch::channel(make_overlay_command(&config));
+    let supervisor = Supervisor::new(manager, info_receiver.clone(), overlay_command_receiver);
+    let line_server = LineServer {
+        manager,
+        tokens,
+        info_updates: info_receiver,
+    };
+
+    if config.bind_on.is_none()
+        && config.socket_path.is_none()
+        && config.line_bind_on.is_none()
+        && config.line_socket_path.is_none()
+    {
+        eprintln!("No listeners are configured, so there is nothing to listen on");
+        std::process::exit(1);
+    }
+    let tcp_server = async {
+        if let Some(bind_on) = &config.bind_on {
+            eprintln!("Server is listening on {}", bind_on);
+            let listener = tokio::net::TcpListener::bind(bind_on).await.unwrap();
+            axum::serve(listener, app.clone()).await.unwrap();
+        }
+    };
+    let unix_server = async {
+        if let Some(socket_path) = &config.socket_path {
+            #[cfg(unix)]
+            crate::unix_socket::serve(
+                socket_path,
+                config.socket_mode.unwrap_or(0o600),
+                app.clone(),
+            )
+            .await;
+            #[cfg(not(unix))]
+            eprintln!(
+                "Not listening on {} because Unix sockets are not supported on this platform",
+                socket_path.display()
+            );
+        }
+    };
+    let line_tcp_server = async {
+        if let Some(line_bind_on) = &config.line_bind_on {
+            line_server.clone().serve_tcp(line_bind_on).await;
+        }
+    };
+    let line_unix_server = async {
+        if let Some(line_socket_path) = &config.line_socket_path {
+            #[cfg(unix)]
+            line_server
+                .clone()
+                .serve_unix(line_socket_path, config.socket_mode.unwrap_or(0o600))
+                .await;
+            #[cfg(not(unix))]
+            eprintln!(
+                "Not listening on {} because Unix sockets are not supported on this platform",
+                line_socket_path.display()
+            );
+        }
+    };
     let watch_for_changes = async {
-        let mut cache_version = DiagonatorManager::NO_CACHE;
+        let mut cache_version = info_sender.borrow().1;
         loop {
-            if let Some((new_info, new_version)) = manager
-                .lock()
-                .unwrap()
-                .get_info_if_changed(cache_version, Timestamp::now())
-            {
-                io.emit("info_update", new_info).unwrap();
+            let (changed, warnings) = {
+                let mut manager = manager.lock().unwrap();
+                let changed = manager.get_info_if_changed(cache_version, Timestamp::now());
+                (changed, manager.take_lock_warnings())
+            };
+            if let Some((new_info, new_version)) = changed {
+                io.emit("info_update", &new_info).unwrap();
+                info_sender.send_replace((new_info, new_version));
                 cache_version = new_version;
             }
+            for warning in warnings {
+                io.emit("lock_warning", &warning).unwrap();
+            }
             tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         }
     };
-    tokio::join!(server, watch_for_changes);
+    let watch_config_file = async {
+        let mut last_modified = modified_time(&config_file_path);
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            let modified = modified_time(&config_file_path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            eprintln!(
+                "Reloading configuration from {}",
+                config_file_path.display()
+            );
+            match read_config(&config_file_path) {
+                Ok(new_config) => {
+                    if new_config.bind_on != config.bind_on
+                        || new_config.socket_path != config.socket_path
+                        || new_config.socket_mode != config.socket_mode
+                        || new_config.line_bind_on != config.line_bind_on
+                        || new_config.line_socket_path != config.line_socket_path
+                    {
+                        eprintln!("Restart the server to apply the new listener settings");
+                    }
+                    manager
+                        .lock()
+                        .unwrap()
+                        .reload_config(Timestamp::now(), make_manager_config(&new_config));
+                    overlay_command_sender.send_replace(make_overlay_command(&new_config));
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Encountered error when reloading config, keeping the previous configuration: {}",
+                        err
+                    );
+                }
+            }
+        }
+    };
+    tokio::join!(
+        tcp_server,
+        unix_server,
+        line_tcp_server,
+        line_unix_server,
+        watch_for_changes,
+        watch_config_file,
+        supervisor.run()
+    );
 }
@@ -1,5 +1,6 @@
-use crate::manager::{CurrentState, CurrentStateReason};
+use crate::manager::{CurrentState, CurrentStateReason, ReasonKind};
 use crate::time::Timestamp;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
 pub enum StateChangeKind {
@@ -16,26 +17,19 @@ pub struct StateChange {
     pub time: Timestamp,
 }
 
+/// The IDs of the active locks. A lock that is added twice has to be unlocked twice.
 struct Locks {
     locks: Vec<u64>,
 }
 
 impl Locks {
     fn add_lock(&mut self, id: u64) {
-        assert!(
-            !self.locks.iter().any(|&lock_id| lock_id == id),
-            "duplicate lock with id {}",
-            id
-        );
         self.locks.push(id);
     }
     fn unlock(&mut self, id: u64) {
-        let index = self
-            .locks
-            .iter()
-            .position(|&lock_id| lock_id == id)
-            .expect(&format!("lock with id {} not found", id));
-        self.locks.remove(index);
+        if let Some(index) = self.locks.iter().position(|&lock_id| lock_id == id) {
+            self.locks.remove(index);
+        }
     }
     fn is_empty(&self) -> bool {
         self.locks.is_empty()
@@ -52,9 +46,33 @@ impl Locks {
 pub struct SimulatorResult {
     pub target_state: CurrentState,
     pub until: Option<Timestamp>,
+    pub next_state: Option<CurrentState>,
+    pub reason: CurrentStateReason,
+}
+
+/// A period of time during which the state stays the same.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TimelineSegment {
+    pub state: CurrentState,
+    pub start: Timestamp,
+    pub end: Option<Timestamp>,
     pub reason: CurrentStateReason,
 }
 
+/// The result of applying all of the state changes that happen at a certain time.
+struct SimulationStep {
+    time: Timestamp,
+    state: CurrentState,
+    /// the reason for being in `state` after the changes
+    reason: CurrentStateReason,
+    /// the reason for the state change, if the state changed
+    change_reason: Option<CurrentStateReason>,
+    /// every kind of constraint that is in effect after the changes, not only the one that
+    /// determines `reason`
+    constraints: Vec<ReasonKind>,
+}
+
+#[derive(Default)]
 pub struct Simulator {
     changes: Vec<StateChange>,
 }
@@ -69,6 +87,79 @@ impl Simulator {
         self.changes.push(change);
     }
     pub fn run(&mut self, target_time: Timestamp) -> SimulatorResult {
+        let mut target_state = CurrentState::Unlocked;
+        let mut reason = CurrentStateReason::NoConstraints;
+        for step in self.simulate() {
+            if step.time <= target_time {
+                target_state = step.state;
+                reason = step.reason;
+            } else if let Some(change_reason) = step.change_reason {
+                return SimulatorResult {
+                    target_state,
+                    until: Some(step.time),
+                    next_state: Some(step.state),
+                    reason: change_reason,
+                };
+            }
+        }
+        SimulatorResult {
+            target_state,
+            until: None,
+            next_state: None,
+            reason,
+        }
+    }
+    /// Returns the segments of time from `start_time` onwards, in chronological order. A new
+    /// segment starts whenever the state or the reason for it changes, and the last segment has
+    /// no end.
+    pub fn timeline(&mut self, start_time: Timestamp) -> Vec<TimelineSegment> {
+        let mut segments = Vec::new();
+        let mut current = TimelineSegment {
+            state: CurrentState::Unlocked,
+            start: start_time,
+            end: None,
+            reason: CurrentStateReason::NoConstraints,
+        };
+        for step in self.simulate() {
+            if step.time <= start_time {
+                current.state = step.state;
+                current.reason = step.reason;
+            } else if step.change_reason.is_some() || step.reason != current.reason {
+                let next = TimelineSegment {
+                    state: step.state,
+                    start: step.time,
+                    end: None,
+                    reason: step.reason,
+                };
+                current.end = Some(step.time);
+                segments.push(std::mem::replace(&mut current, next));
+            }
+        }
+        segments.push(current);
+        segments
+    }
+    /// Returns every kind of constraint that is in effect at some point from `start_time` until
+    /// `end_time`, including ones that are hidden behind a reason with a higher priority.
+    pub fn constraints_between(
+        &mut self,
+        start_time: Timestamp,
+        end_time: Timestamp,
+    ) -> Vec<ReasonKind> {
+        let mut constraints = vec![ReasonKind::NoConstraints];
+        for step in self.simulate() {
+            if step.time <= start_time {
+                constraints = step.constraints;
+            } else if step.time < end_time {
+                for constraint in step.constraints {
+                    if !constraints.contains(&constraint) {
+                        constraints.push(constraint);
+                    }
+                }
+            }
+        }
+        constraints
+    }
+    fn simulate(&mut self) -> Vec<SimulationStep> {
         // stable sort preserves original order of state changes with the same time
         // state changes that were pushed earlier get higher priority when determining the reason
         self.changes.sort_by_key(|sc| sc.time);
@@ -76,58 +167,108 @@ impl Simulator {
         let mut locked_requirements = Locks::new();
         let mut break_timer_state = CurrentState::Unlocked;
         let mut simulator_state = CurrentState::Unlocked;
-        let mut simulator_result: Option<SimulatorResult> = None;
-        for change in &self.changes {
-            use StateChangeKind::*;
-            match change.kind {
-                BreakTimerUnlockable => break_timer_state = CurrentState::Unlockable,
-                BreakTimerLocked => break_timer_state = CurrentState::Locked,
-                RangeLocked(id) => locked_ranges.add_lock(id),
-                RangeUnlocked(id) => locked_ranges.unlock(id),
-                RequirementLocked(id) => locked_requirements.add_lock(id),
+        let mut steps = Vec::new();
+        let mut changes = self.changes.iter().peekable();
+        while let Some(first_change) = changes.next() {
+            // all of the changes that happen at the same time are applied together, so that
+            // a lock that ends exactly when another one begins doesn't count as a state change
+            let mut group = vec![first_change];
+            while let Some(change) = changes.next_if(|change| change.time == first_change.time) {
+                group.push(change);
+            }
+            // the first change in the group that alters the state determines the reason
+            let mut deciding_change: Option<StateChangeKind> = None;
+            for change in group {
+                use StateChangeKind::*;
+                match change.kind {
+                    BreakTimerUnlockable => break_timer_state = CurrentState::Unlockable,
+                    BreakTimerLocked => break_timer_state = CurrentState::Locked,
+                    RangeLocked(id) => locked_ranges.add_lock(id),
+                    RangeUnlocked(id) => locked_ranges.unlock(id),
+                    RequirementLocked(id) => locked_requirements.add_lock(id),
+                }
+                if deciding_change.is_none()
+                    && simulator_state
+                        != Self::calc_state(&locked_ranges, &locked_requirements, break_timer_state)
+                {
+                    deciding_change = Some(change.kind);
+                }
             }
             let state_after_change =
                 Self::calc_state(&locked_ranges, &locked_requirements, break_timer_state);
-            if simulator_state != state_after_change {
-                if change.time > target_time {
-                    simulator_result = Some(SimulatorResult {
-                        target_state: simulator_state,
-                        until: Some(change.time),
-                        reason: match change.kind {
-                            StateChangeKind::BreakTimerUnlockable
-                            | StateChangeKind::BreakTimerLocked => CurrentStateReason::BreakTimer,
-                            StateChangeKind::RangeLocked(id)
-                            | StateChangeKind::RangeUnlocked(id) => {
-                                CurrentStateReason::LockedTimeRange { id }
-                            }
-                            StateChangeKind::RequirementLocked(id) => {
-                                CurrentStateReason::RequirementNotMet { id }
-                            }
-                        },
-                    });
-                    break;
+            let change_reason = if simulator_state != state_after_change {
+                Some(match deciding_change.unwrap_or(first_change.kind) {
+                    StateChangeKind::BreakTimerUnlockable | StateChangeKind::BreakTimerLocked => {
+                        CurrentStateReason::BreakTimer
+                    }
+                    StateChangeKind::RangeLocked(id) | StateChangeKind::RangeUnlocked(id) => {
+                        CurrentStateReason::LockedTimeRange { id }
+                    }
+                    StateChangeKind::RequirementLocked(id) => {
+                        CurrentStateReason::RequirementNotMet { id }
+                    }
+                })
+            } else {
+                None
+            };
+            simulator_state = state_after_change;
+            steps.push(SimulationStep {
+                time: first_change.time,
+                state: simulator_state,
+                reason: Self::calc_reason(simulator_state, &locked_ranges, &locked_requirements),
+                change_reason,
+                constraints: Self::calc_constraints(
+                    &locked_ranges,
+                    &locked_requirements,
+                    break_timer_state,
+                ),
+            });
+        }
+        steps
+    }
+    fn calc_reason(
+        state: CurrentState,
+        locked_ranges: &Locks,
+        locked_requirements: &Locks,
+    ) -> CurrentStateReason {
+        match state {
+            CurrentState::Unlocked => CurrentStateReason::NoConstraints,
+            CurrentState::Unlockable => CurrentStateReason::BreakTimer,
+            CurrentState::Locked => {
+                if let Some(id) = locked_requirements.first() {
+                    CurrentStateReason::RequirementNotMet { id }
+                } else if let Some(id) = locked_ranges.first() {
+                    CurrentStateReason::LockedTimeRange { id }
                 } else {
-                    simulator_state = state_after_change;
+                    CurrentStateReason::BreakTimer
                 }
             }
         }
-        simulator_result.unwrap_or(SimulatorResult {
-            target_state: simulator_state,
-            until: None,
-            reason: match simulator_state {
-                CurrentState::Unlocked => CurrentStateReason::NoConstraints,
-                CurrentState::Unlockable => CurrentStateReason::BreakTimer,
-                CurrentState::Locked => {
-                    if let Some(id) = locked_requirements.first() {
-                        CurrentStateReason::RequirementNotMet { id }
-                    } else if let Some(id) = locked_ranges.first() {
-                        CurrentStateReason::LockedTimeRange { id }
-                    } else {
-                        CurrentStateReason::BreakTimer
-                    }
-                }
-            },
-        })
+    }
+    fn calc_constraints(
+        locked_ranges: &Locks,
+        locked_requirements: &Locks,
+        break_timer_state: CurrentState,
+    ) -> Vec<ReasonKind> {
+        let mut constraints = Vec::new();
+        if !locked_requirements.is_empty() {
+            constraints.push(ReasonKind::RequirementNotMet);
+        }
+        if !locked_ranges.is_empty() {
+            constraints.push(ReasonKind::LockedTimeRange);
+        }
+        // an unlockable break timer only matters if nothing else is locking the screen
+        match break_timer_state {
+            CurrentState::Locked => constraints.push(ReasonKind::BreakTimer),
+            CurrentState::Unlockable if constraints.is_empty() => {
+                constraints.push(ReasonKind::BreakTimer)
+            }
+            _ => {}
+        }
+        if constraints.is_empty() {
+            constraints.push(ReasonKind::NoConstraints);
+        }
+        constraints
     }
     fn calc_state(
         locked_ranges: &Locks,
@@ -141,3 +282,195 @@ impl Simulator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::Duration;
+
+    fn at(minutes: i64) -> Timestamp {
+        Timestamp::ZERO + Duration::from_minutes(minutes)
+    }
+
+    fn simulator(changes: &[(StateChangeKind, i64)]) -> Simulator {
+        let mut simulator = Simulator::new();
+        for (kind, minutes) in changes {
+            simulator.push(StateChange {
+                kind: *kind,
+                time: at(*minutes),
+            });
+        }
+        simulator
+    }
+
+    #[test]
+    fn adjacent_locks_are_not_a_state_change() {
+        use StateChangeKind::*;
+        let result = simulator(&[
+            (RangeLocked(1), 60),
+            (RangeUnlocked(1), 120),
+            (RangeLocked(2), 120),
+            (RangeUnlocked(2), 180),
+            (BreakTimerUnlockable, 0),
+        ])
+        .run(at(90));
+        assert_eq!(result.target_state, CurrentState::Locked);
+        assert_eq!(result.until, Some(at(180)));
+        assert_eq!(result.next_state, Some(CurrentState::Unlockable));
+        assert_eq!(result.reason, CurrentStateReason::LockedTimeRange { id: 2 });
+    }
+
+    #[test]
+    fn simultaneous_changes_use_the_first_pushed_reason() {
+        use StateChangeKind::*;
+        let result = simulator(&[
+            (RequirementLocked(5), 60),
+            (RangeLocked(7), 60),
+            (BreakTimerLocked, 60),
+        ])
+        .run(at(0));
+        assert_eq!(result.target_state, CurrentState::Unlocked);
+        assert_eq!(result.until, Some(at(60)));
+        assert_eq!(result.next_state, Some(CurrentState::Locked));
+        assert_eq!(
+            result.reason,
+            CurrentStateReason::RequirementNotMet { id: 5 }
+        );
+    }
+
+    #[test]
+    fn duplicate_locks_do_not_panic() {
+        use StateChangeKind::*;
+        let result = simulator(&[
+            (RequirementLocked(1), 60),
+            (RequirementLocked(1), 90),
+            (RangeLocked(2), 120),
+            (RangeLocked(2), 120),
+            (RangeUnlocked(2), 150),
+        ])
+        .run(at(200));
+        assert_eq!(result.target_state, CurrentState::Locked);
+        assert_eq!(
+            result.reason,
+            CurrentStateReason::RequirementNotMet { id: 1 }
+        );
+    }
+
+    #[test]
+    fn changes_after_midnight_are_predicted() {
+        use StateChangeKind::*;
+        // a range from 22:00 to 04:30 and a requirement that is due at 08:00 on the next day
+        let changes = [
+            (RangeLocked(1), 22 * 60),
+            (RangeUnlocked(1), 28 * 60 + 30),
+            (RequirementLocked(2), 32 * 60),
+        ];
+        let result = simulator(&changes).run(at(23 * 60));
+        assert_eq!(result.target_state, CurrentState::Locked);
+        assert_eq!(result.until, Some(at(28 * 60 + 30)));
+        assert_eq!(result.next_state, Some(CurrentState::Unlocked));
+        assert_eq!(result.reason, CurrentStateReason::LockedTimeRange { id: 1 });
+
+        let result = simulator(&changes).run(at(29 * 60));
+        assert_eq!(result.target_state, CurrentState::Unlocked);
+        assert_eq!(result.until, Some(at(32 * 60)));
+        assert_eq!(result.next_state, Some(CurrentState::Locked));
+        assert_eq!(
+            result.reason,
+            CurrentStateReason::RequirementNotMet { id: 2 }
+        );
+    }
+
+    #[test]
+    fn constraints_include_locks_hidden_by_other_reasons() {
+        use StateChangeKind::*;
+        // an overdue requirement hides a range that is active at the same time
+        let changes = [
+            (RequirementLocked(1), 60),
+            (RangeLocked(2), 120),
+            (RangeUnlocked(2), 180),
+            (BreakTimerUnlockable, 0),
+        ];
+        assert_eq!(
+            simulator(&changes).constraints_between(at(150), at(160)),
+            vec![ReasonKind::RequirementNotMet, ReasonKind::LockedTimeRange]
+        );
+        assert_eq!(
+            simulator(&changes).constraints_between(at(30), at(120)),
+            vec![ReasonKind::BreakTimer, ReasonKind::RequirementNotMet]
+        );
+        assert_eq!(
+            simulator(&changes).constraints_between(at(90), at(121)),
+            vec![ReasonKind::RequirementNotMet, ReasonKind::LockedTimeRange]
+        );
+        // a break that overlaps a range still counts
+        assert_eq!(
+            simulator(&[(RangeLocked(3), 60), (BreakTimerLocked, 90)])
+                .constraints_between(at(100), at(110)),
+            vec![ReasonKind::LockedTimeRange, ReasonKind::BreakTimer]
+        );
+        assert_eq!(
+            simulator(&[(RangeLocked(3), 60)]).constraints_between(at(0), at(30)),
+            vec![ReasonKind::NoConstraints]
+        );
+    }
+
+    #[test]
+    fn timeline_splits_segments_when_the_reason_changes() {
+        use StateChangeKind::*;
+        // lunch from 12:00 to 13:00, followed by a requirement that is due at 13:00
+        let segments = simulator(&[
+            (RequirementLocked(2), 13 * 60),
+            (RangeLocked(1), 12 * 60),
+            (RangeUnlocked(1), 13 * 60),
+        ])
+        .timeline(at(9 * 60));
+        assert_eq!(
+            segments,
+            vec![
+                TimelineSegment {
+                    state: CurrentState::Unlocked,
+                    start: at(9 * 60),
+                    end: Some(at(12 * 60)),
+                    reason: CurrentStateReason::NoConstraints,
+                },
+                TimelineSegment {
+                    state: CurrentState::Locked,
+                    start: at(12 * 60),
+                    end: Some(at(13 * 60)),
+                    reason: CurrentStateReason::LockedTimeRange { id: 1 },
+                },
+                TimelineSegment {
+                    state: CurrentState::Locked,
+                    start: at(13 * 60),
+                    end: None,
+                    reason: CurrentStateReason::RequirementNotMet { id: 2 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn timeline_starts_with_the_current_state() {
+        use StateChangeKind::*;
+        let segments = simulator(&[(BreakTimerLocked, 0), (BreakTimerUnlockable, 10 * 60 + 5)])
+            .timeline(at(10 * 60));
+        assert_eq!(
+            segments,
+            vec![
+                TimelineSegment {
+                    state: CurrentState::Locked,
+                    start: at(10 * 60),
+                    end: Some(at(10 * 60 + 5)),
+                    reason: CurrentStateReason::BreakTimer,
+                },
+                TimelineSegment {
+                    state: CurrentState::Unlockable,
+                    start: at(10 * 60 + 5),
+                    end: None,
+                    reason: CurrentStateReason::BreakTimer,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use crate::config::{config_dir, LoadConfigError};
+use crate::manager::ManagerSnapshot;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Bump this whenever the layout of `ManagerSnapshot` changes, so that state files written by an
+/// older version of the server are discarded instead of being misinterpreted.
+const STATE_VERSION: u32 = 11;
+
+#[derive(Serialize)]
+struct StateFileContents<'a> {
+    version: u32,
+    snapshot: &'a ManagerSnapshot,
+}
+
+#[derive(Deserialize)]
+struct StateFileHeader {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct VersionedSnapshot {
+    snapshot: ManagerSnapshot,
+}
+
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn in_config_dir() -> Result<Self, LoadConfigError> {
+        let mut path = config_dir()?;
+        path.push("state.json");
+        Ok(Self { path })
+    }
+    pub fn load(&self) -> Option<ManagerSnapshot> {
+        if !self.path.exists() {
+            return None;
+        }
+        eprintln!("Loading saved state from {}", self.path.display());
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!(
+                    "Received error '{}' when reading from file {}, ignoring saved state",
+                    err,
+                    self.path.display()
+                );
+                return None;
+            }
+        };
+        match serde_json::from_str::<StateFileHeader>(&contents) {
+            Ok(header) if header.version == STATE_VERSION => {}
+            Ok(header) => {
+                eprintln!(
+                    "Saved state has version {} but version {} is expected, ignoring saved state",
+                    header.version, STATE_VERSION
+                );
+                return None;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Received error '{}' when deserializing saved state, ignoring saved state",
+                    err
+                );
+                return None;
+            }
+        }
+        match serde_json::from_str::<VersionedSnapshot>(&contents) {
+            Ok(versioned) => Some(versioned.snapshot),
+            Err(err) => {
+                eprintln!(
+                    "Received error '{}' when deserializing saved state, ignoring saved state",
+                    err
+                );
+                None
+            }
+        }
+    }
+    pub fn save(&self, snapshot: &ManagerSnapshot) {
+        let contents = match serde_json::to_string_pretty(&StateFileContents {
+            version: STATE_VERSION,
+            snapshot,
+        }) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("Received error '{}' when serializing state", err);
+                return;
+            }
+        };
+        // write to a temporary file first so that a crash in the middle of writing
+        // never leaves behind a truncated state file
+        let tmp_path = self.path.with_extension("json.tmp");
+        if let Err(err) = fs::write(&tmp_path, contents) {
+            eprintln!(
+                "Received error '{}' when writing to file {}",
+                err,
+                tmp_path.display()
+            );
+            return;
+        }
+        if let Err(err) = fs::rename(&tmp_path, &self.path) {
+            eprintln!(
+                "Received error '{}' when renaming {} to {}",
+                err,
+                tmp_path.display(),
+                self.path.display()
+            );
+        }
+    }
+}
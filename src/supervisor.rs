@@ -0,0 +1,151 @@
+use crate::manager::{CurrentInfo, DiagonatorManager};
+use crate::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+/// How long to wait before restarting the lock screen after it exits unexpectedly.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+/// How long to wait before trying again after the lock screen fails to start.
+const SPAWN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The command that displays the lock screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayCommand {
+    pub path: String,
+    pub args: Vec<String>,
+}
+
+/// The health of the lock screen process, as reported in `CurrentInfo`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlayStatus {
+    running: bool,
+    pid: Option<u32>,
+    /// the number of times that the process had to be restarted because it exited while it
+    /// should have been running
+    restarts: u32,
+    /// the most recent failure to start the process, or its most recent unexpected exit
+    last_error: Option<String>,
+}
+
+/// Starts the lock screen whenever `diagonator_running` becomes true, stops it when it becomes
+/// false, and restarts it if it exits in between, so that killing it doesn't unlock the screen.
+pub struct Supervisor {
+    manager: &'static Mutex<DiagonatorManager>,
+    info_updates: watch::Receiver<(CurrentInfo, u64)>,
+    commands: watch::Receiver<Option<OverlayCommand>>,
+    child: Option<Child>,
+    /// the command that `child` was started with
+    child_command: Option<OverlayCommand>,
+    status: OverlayStatus,
+    /// the lock screen isn't started again before this time
+    retry_at: Option<Instant>,
+}
+
+impl Supervisor {
+    pub fn new(
+        manager: &'static Mutex<DiagonatorManager>,
+        info_updates: watch::Receiver<(CurrentInfo, u64)>,
+        commands: watch::Receiver<Option<OverlayCommand>>,
+    ) -> Self {
+        Self {
+            manager,
+            info_updates,
+            commands,
+            child: None,
+            child_command: None,
+            status: OverlayStatus::default(),
+            retry_at: None,
+        }
+    }
+    pub async fn run(mut self) {
+        let mut reported_status = None;
+        loop {
+            let should_run = self.info_updates.borrow().0.diagonator_running();
+            let command = self.commands.borrow().clone();
+            self.reap(should_run);
+            match (&command, self.child.take()) {
+                (Some(command), None) if should_run => {
+                    if self
+                        .retry_at
+                        .is_none_or(|retry_at| Instant::now() >= retry_at)
+                    {
+                        self.spawn(command);
+                    }
+                }
+                (Some(command), Some(child))
+                    if should_run && self.child_command.as_ref() == Some(command) =>
+                {
+                    self.child = Some(child)
+                }
+                (_, Some(mut child)) => {
+                    // the lock screen is started again with the new command on the next
+                    // iteration if it should still be running
+                    if should_run {
+                        eprintln!("Restarting lock screen because its command changed");
+                    }
+                    if let Err(err) = child.kill().await {
+                        eprintln!("Received error '{}' when stopping lock screen", err);
+                    }
+                    self.retry_at = None;
+                }
+                (_, None) => self.retry_at = None,
+            }
+            self.status.running = self.child.is_some();
+            self.status.pid = self.child.as_ref().and_then(Child::id);
+            let status = command.map(|_| self.status.clone());
+            if status != reported_status {
+                self.manager
+                    .lock()
+                    .unwrap()
+                    .set_overlay_status(Timestamp::now(), status.clone());
+                reported_status = status;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+    /// Checks whether the lock screen has exited.
+    fn reap(&mut self, should_run: bool) {
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        let exit_status = match child.try_wait() {
+            Ok(Some(exit_status)) => exit_status,
+            Ok(None) => return,
+            Err(err) => {
+                eprintln!("Received error '{}' when checking on lock screen", err);
+                return;
+            }
+        };
+        self.child = None;
+        if should_run {
+            eprintln!("Lock screen exited unexpectedly with {}", exit_status);
+            self.status.restarts += 1;
+            self.status.last_error = Some(format!("exited unexpectedly with {}", exit_status));
+            self.retry_at = Some(Instant::now() + RESTART_DELAY);
+        }
+    }
+    fn spawn(&mut self, command: &OverlayCommand) {
+        match Command::new(&command.path)
+            .args(&command.args)
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => {
+                self.child = Some(child);
+                self.child_command = Some(command.clone());
+                self.retry_at = None;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Received error '{}' when starting lock screen {}",
+                    err, command.path
+                );
+                self.status.last_error = Some(format!("failed to start: {}", err));
+                self.retry_at = Some(Instant::now() + SPAWN_RETRY_DELAY);
+            }
+        }
+    }
+}
@@ -1,17 +1,19 @@
-use chrono::TimeZone;
+use chrono::{Offset, TimeZone};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::ops::Add;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HourMinute {
     hour: u32,
     minute: u32,
 }
 
 impl HourMinute {
+    pub const MIDNIGHT: Self = Self { hour: 0, minute: 0 };
     pub fn new(hour: u32, minute: u32) -> Option<Self> {
         if (0..=23).contains(&hour) && (0..=59).contains(&minute) {
             Some(Self { hour, minute })
@@ -19,6 +21,18 @@ impl HourMinute {
             None
         }
     }
+    pub fn hour(self) -> u32 {
+        self.hour
+    }
+    pub fn minute(self) -> u32 {
+        self.minute
+    }
+}
+
+impl std::fmt::Display for HourMinute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
 }
 
 impl Serialize for HourMinute {
@@ -26,59 +40,116 @@ impl Serialize for HourMinute {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!("{:02}:{:02}", self.hour, self.minute))
+        serializer.serialize_str(&self.to_string())
     }
 }
 
-impl<'de> Deserialize<'de> for HourMinute {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let val = String::deserialize(deserializer)?;
+impl FromStr for HourMinute {
+    type Err = String;
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
         lazy_static! {
             static ref RE: Regex = Regex::new(r"^(\d?\d):(\d\d)$").unwrap();
         }
-        if let Some(captured) = RE.captures(&val) {
+        if let Some(captured) = RE.captures(val) {
             if let (Some(h), Some(m)) = (captured.get(1), captured.get(2)) {
                 if let Some(hm) =
                     HourMinute::new(h.as_str().parse().unwrap(), m.as_str().parse().unwrap())
                 {
                     return Ok(hm);
                 } else {
-                    return Err(D::Error::custom(format!("Time is out of range: '{}'", val)));
+                    return Err(format!("Time is out of range: '{}'", val));
                 }
             }
         }
-        Err(D::Error::custom(format!(
-            "Failed to parse time from string: '{}'",
-            val
-        )))
+        Err(format!("Failed to parse time from string: '{}'", val))
+    }
+}
+
+impl<'de> Deserialize<'de> for HourMinute {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = String::deserialize(deserializer)?;
+        val.parse().map_err(D::Error::custom)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Duration(i64);
 
 impl Duration {
+    pub const ZERO: Self = Self(0);
+    pub fn from_seconds(seconds: i64) -> Self {
+        Self(seconds)
+    }
     pub fn from_minutes(minutes: i64) -> Self {
         Self(minutes * 60)
     }
+    pub fn as_secs(self) -> i64 {
+        self.0
+    }
+}
+
+impl Sub for Duration {
+    type Output = Self;
+    fn sub(self, rhs: Duration) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Add for Duration {
+    type Output = Self;
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self(self.0 + rhs.0)
+    }
 }
+
+impl std::iter::Sum for Duration {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |a, b| a + b)
+    }
+}
+
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (amount, unit) = if self.0 % 60 == 0 {
+            (self.0 / 60, "minute")
+        } else {
+            (self.0, "second")
+        };
+        if amount == 1 {
+            write!(f, "1 {}", unit)
+        } else {
+            write!(f, "{} {}s", amount, unit)
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Timestamp(i64);
 
 impl Timestamp {
     pub const ZERO: Self = Self(0);
+    /// Returns the first moment at which the local time is `hm` on `date`. A time that is
+    /// skipped by a DST change is converted with the offset from just before or after the gap,
+    /// rather than panicking like `Date::and_hms` does.
     pub fn from_date_hm(date: &LocalDate, hm: &HourMinute) -> Self {
-        Self(date.and_hms(hm.hour, hm.minute, 0).timestamp())
+        let naive = date.naive_local().and_hms(hm.hour, hm.minute, 0);
+        match chrono::Local.from_local_datetime(&naive).earliest() {
+            Some(datetime) => Self(datetime.timestamp()),
+            None => {
+                let offset = chrono::Local.offset_from_utc_datetime(&naive);
+                Self(naive.timestamp() - i64::from(offset.fix().local_minus_utc()))
+            }
+        }
+    }
+    /// Returns the start of `date`, which is usually midnight.
+    pub fn start_of_day(date: &LocalDate) -> Self {
+        Self::from_date_hm(date, &HourMinute::MIDNIGHT)
     }
     pub fn from_date_hm_opt(date: &LocalDate, hm: &Option<HourMinute>) -> Option<Self> {
-        if let Some(hm) = hm {
-            Some(Self(date.and_hms(hm.hour, hm.minute, 0).timestamp()))
-        } else {
-            None
-        }
+        hm.as_ref().map(|hm| Self::from_date_hm(date, hm))
     }
     pub fn now() -> Self {
         Self(chrono::Local::now().timestamp())
@@ -86,6 +157,31 @@ impl Timestamp {
     pub fn get_date(self) -> LocalDate {
         chrono::Local.timestamp(self.0, 0).date()
     }
+    /// Returns the local date as a string of the form YYYY-MM-DD, along with
+    /// the number of seconds since midnight.
+    pub fn get_date_and_seconds(self) -> (String, i64) {
+        let date = self.get_date();
+        let midnight = Self::start_of_day(&date);
+        (date.format("%Y-%m-%d").to_string(), self.0 - midnight.0)
+    }
+    /// Formats the local time as HH:MM.
+    pub fn format_hm(self) -> String {
+        chrono::Local
+            .timestamp(self.0, 0)
+            .format("%H:%M")
+            .to_string()
+    }
+    /// Returns the local time of day.
+    pub fn get_hm(self) -> HourMinute {
+        let time = chrono::Local.timestamp(self.0, 0);
+        HourMinute {
+            hour: chrono::Timelike::hour(&time),
+            minute: chrono::Timelike::minute(&time),
+        }
+    }
+    pub fn as_secs(self) -> i64 {
+        self.0
+    }
 }
 
 impl Add<Duration> for Timestamp {
@@ -95,4 +191,36 @@ impl Add<Duration> for Timestamp {
     }
 }
 
+impl Sub<Duration> for Timestamp {
+    type Output = Self;
+    fn sub(self, rhs: Duration) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+    fn sub(self, rhs: Timestamp) -> Self::Output {
+        Duration(self.0 - rhs.0)
+    }
+}
+
 pub type LocalDate = chrono::Date<chrono::Local>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_day_has_a_start() {
+        // run with e.g. TZ=America/Santiago to cover DST changes at midnight
+        let mut date = Timestamp::ZERO.get_date();
+        for _ in 0..(366 * 60) {
+            let start = Timestamp::start_of_day(&date);
+            assert_eq!(start.get_date(), date);
+            assert_eq!(start.get_date_and_seconds().1, 0);
+            assert_eq!((start - Duration::from_seconds(1)).get_date(), date.pred());
+            date = date.succ();
+        }
+    }
+}
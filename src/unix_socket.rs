@@ -0,0 +1,99 @@
+use axum::{Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::net::UnixListener;
+
+/// Marks requests that were received over the Unix socket. Access to the socket is controlled
+/// by its file permissions, so these requests don't need a token.
+#[derive(Clone, Copy, Debug)]
+pub struct UnixConnection;
+
+/// Prints an error for a failed operation on `path` and exits.
+fn exit_on_error<T>(result: std::io::Result<T>, action: &str, path: &Path) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!(
+            "Received error '{}' when {} {}",
+            err,
+            action,
+            path.display()
+        );
+        std::process::exit(1);
+    })
+}
+
+/// Binds a socket at `path` that can be accessed according to the permissions in `mode`,
+/// replacing any socket left behind by a previous run. Exits on failure.
+pub fn bind(path: &Path, mode: u32) -> UnixListener {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            eprintln!(
+                "Cannot listen on {} because it exists and is not a socket",
+                path.display()
+            );
+            std::process::exit(1);
+        }
+        exit_on_error(fs::remove_file(path), "removing old socket", path);
+    }
+    // the socket is created in a directory that only we can access and is moved into place
+    // once its permissions are set, so that nobody else can connect to it before then
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staging_dir = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    if staging_dir.exists() {
+        exit_on_error(
+            fs::remove_dir_all(&staging_dir),
+            "removing old directory",
+            &staging_dir,
+        );
+    }
+    exit_on_error(
+        fs::DirBuilder::new().mode(0o700).create(&staging_dir),
+        "creating directory",
+        &staging_dir,
+    );
+    let staging_path = staging_dir.join("socket");
+    let listener = exit_on_error(
+        UnixListener::bind(&staging_path),
+        "binding to socket",
+        &staging_path,
+    );
+    exit_on_error(
+        fs::set_permissions(&staging_path, fs::Permissions::from_mode(mode)),
+        "setting permissions of socket",
+        &staging_path,
+    );
+    exit_on_error(fs::rename(&staging_path, path), "moving socket to", path);
+    exit_on_error(
+        fs::remove_dir(&staging_dir),
+        "removing directory",
+        &staging_dir,
+    );
+    listener
+}
+
+pub async fn serve(path: &Path, mode: u32, app: Router) {
+    let listener = bind(path, mode);
+    eprintln!("Server is listening on {}", path.display());
+    let app = app.layer(Extension(UnixConnection));
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                eprintln!("Received error '{}' when accepting connection", err);
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(err) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("Received error '{}' when serving connection", err);
+            }
+        });
+    }
+}
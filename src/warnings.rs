@@ -0,0 +1,75 @@
+use crate::events::reason_details;
+use crate::manager::{CurrentInfo, CurrentState, CurrentStateReason};
+use crate::time::{Duration, Timestamp};
+use serde::{Deserialize, Serialize};
+
+/// A warning that the screen is about to be locked.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockWarning {
+    /// when the screen will be locked
+    pub locks_at: Timestamp,
+    /// the threshold that was reached
+    pub threshold: Duration,
+    /// the reason for the upcoming lock
+    pub reason: CurrentStateReason,
+    /// the name of the requirement, if the lock is caused by one
+    pub requirement: Option<String>,
+}
+
+#[derive(Default)]
+pub struct LockWarningPolicy {
+    /// how long before a lock to send warnings
+    pub thresholds: Vec<Duration>,
+    /// command to run for each warning
+    pub command: Option<String>,
+    pub args: Vec<String>,
+}
+
+/// Keeps track of which warnings were already sent, so that each threshold is only reached
+/// once per lock.
+#[derive(Default)]
+pub struct LockWarnings {
+    /// the time of the upcoming lock and the smallest threshold that was reached for it
+    warned: Option<(Timestamp, Duration)>,
+}
+
+impl LockWarnings {
+    /// Returns a warning if a new threshold has been reached before the next lock. If several
+    /// thresholds were reached at once, only the smallest one is reported.
+    pub fn check(
+        &mut self,
+        policy: &LockWarningPolicy,
+        current_time: Timestamp,
+        info: &CurrentInfo,
+    ) -> Option<LockWarning> {
+        let locks_at = match (info.state(), info.next_state(), info.until()) {
+            (CurrentState::Unlocked, Some(CurrentState::Locked), Some(until)) => until,
+            _ => return None,
+        };
+        // the lock won't be enforced during a deactivation
+        if info
+            .deactivated_until()
+            .is_some_and(|deactivated_until| deactivated_until >= locks_at)
+        {
+            return None;
+        }
+        let remaining = locks_at - current_time;
+        let threshold = *policy
+            .thresholds
+            .iter()
+            .filter(|threshold| remaining <= **threshold)
+            .min()?;
+        if let Some((warned_lock, warned_threshold)) = self.warned {
+            if warned_lock == locks_at && warned_threshold <= threshold {
+                return None;
+            }
+        }
+        self.warned = Some((locks_at, threshold));
+        Some(LockWarning {
+            locks_at,
+            threshold,
+            reason: info.reason().clone(),
+            requirement: reason_details(info),
+        })
+    }
+}